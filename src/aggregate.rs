@@ -2,11 +2,13 @@ use core::panic;
 
 use crate::expression::Expression;
 use crate::function::{CFunction, CodegenFunctionCx};
+use crate::simd;
 use crate::stmt::{handle_operand, handle_place};
 use crate::ty::CType;
 use crate::utils::truncate_to_size;
 use rustc_index::IndexVec;
 use rustc_middle::mir::{AggregateKind, Operand, Place};
+use rustc_middle::ty::ParamEnv;
 use rustc_target::abi::FieldIdx;
 use tracing::{debug, debug_span, error, warn};
 pub fn handle_aggregate<'tcx, 'ccx>(
@@ -73,6 +75,9 @@ pub fn handle_aggregate<'tcx, 'ccx>(
         }
 
         AggregateKind::Adt(_, variant_idx, _, _, active_field_idx) => match var_type {
+            CType::Struct(_) if ty.is_simd() => {
+                simd::handle_simd_aggregate(fn_cx, place, &ty, fields.iter().collect())
+            }
             CType::Struct(struct_info) => {
                 let mut field_expressions = Vec::new();
                 for field in fields {
@@ -264,6 +269,35 @@ pub fn handle_aggregate<'tcx, 'ccx>(
             }
         }
 
+        // ptr::from_raw_parts, a data pointer and the metadata of the pointee
+        AggregateKind::RawPtr(pointee, _) => {
+            let pointee = fn_cx.monomorphize(pointee);
+            let ptr_ty = fn_cx.monomorphize(place.ty(&fn_cx.mir.local_decls, fn_cx.tcx).ty);
+            let data = handle_operand(fn_cx, &fields[FieldIdx::from_u32(0)]);
+
+            let rhs = if pointee.is_sized(fn_cx.tcx, ParamEnv::reveal_all()) {
+                Expression::Cast {
+                    ty: fn_cx.rust_to_c_type(&ptr_ty),
+                    value: Box::new(data),
+                }
+            } else {
+                let meta = handle_operand(fn_cx, &fields[FieldIdx::from_u32(1)]);
+                Expression::fatptr(
+                    data,
+                    Expression::Cast {
+                        ty: CType::Pointer(Box::new(CType::Void)),
+                        value: Box::new(meta),
+                    },
+                )
+            };
+
+            let lhs = handle_place(fn_cx, place);
+            Expression::Assignment {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            }
+        }
+
         _ => {
             warn!("Unhandled aggregate kind: {:?}", kind);
             Expression::NoOp {}
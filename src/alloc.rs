@@ -1,9 +1,19 @@
 use core::fmt;
 use std::fmt::Debug;
 
+use rustc_const_eval::interpret::ConstAllocation;
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::interpret::{read_target_uint, AllocId, GlobalAlloc};
+use rustc_middle::ty::{Instance, ParamEnv, Ty, TyCtxt};
+use tracing::debug;
+
 use crate::{
+    base::OngoingCodegen,
     crepr::{indent, Representable, RepresentationContext},
-    expression::Expression,
+    expression::{BinOpType, Expression},
+    function::{format_fn_name, CodegenFunctionCx},
+    ty::{CIntTy, CType},
+    vtable,
 };
 
 #[derive(Clone)]
@@ -13,18 +23,79 @@ pub struct StaticAllocation {
     // {ctype} ptr_{offset} = {expr};
     ptrs: Vec<(usize, Expression)>,
     name: String,
+
+    // immutable allocations are emitted with a const qualifier
+    is_const: bool,
+
+    // the bytes are laid out for this alignment, typed pointers into them expect it
+    align: u64,
+
+    // size of each pointer slot, from the target's data layout
+    ptr_size: usize,
+
+    // statics of other crates and extern statics are only declared
+    is_extern: bool,
 }
 
 impl StaticAllocation {
     const BYTES_PREFIX: &'static str = "bytes_";
     const PTRS_PREFIX: &'static str = "ptr_";
 
-    pub fn new(name: String, bytes: Vec<u8>, ptrs: Vec<(usize, Expression)>) -> Self {
-        Self { bytes, ptrs, name }
+    pub fn new(
+        name: String,
+        bytes: Vec<u8>,
+        ptrs: Vec<(usize, Expression)>,
+        align: u64,
+        ptr_size: usize,
+    ) -> Self {
+        Self {
+            bytes,
+            ptrs,
+            name,
+            is_const: false,
+            align,
+            ptr_size,
+            is_extern: false,
+        }
+    }
+
+    pub fn new_extern(name: String) -> Self {
+        Self {
+            bytes: vec![],
+            ptrs: vec![],
+            name,
+            is_const: false,
+            align: 1,
+            ptr_size: 0,
+            is_extern: true,
+        }
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_const(&mut self, is_const: bool) {
+        self.is_const = is_const;
+    }
+
+    /// `extern [const] struct {name}_t {name};`, written ahead of all definitions
+    /// so statics pointing to each other can take each other's address
+    pub fn as_forward_declaration(&self) -> Option<String> {
+        if self.is_extern {
+            return None;
+        }
+
+        let qualifier = if self.is_const { "const " } else { "" };
+        Some(format!(
+            "extern {qualifier}struct {} {};",
+            self.type_name(),
+            self.name
+        ))
     }
 
-    pub fn ptr_size() -> usize {
-        return 8; //TODO: hardcoded pointer size, unsure of where to grab this from
+    fn type_name(&self) -> String {
+        format!("{}_t", self.name)
     }
 }
 
@@ -92,8 +163,17 @@ impl StaticAllocation {
         f: &mut (dyn fmt::Write),
         context: &mut RepresentationContext,
     ) -> fmt::Result {
+        // the type is unknown, only the address of the static is taken
+        if self.is_extern {
+            return write!(f, "extern int8_t {}[];", self.name);
+        }
+
         // prelude
-        write!(f, "struct {{")?;
+        write!(f, "_Alignas({}) ", self.align)?;
+        if self.is_const {
+            write!(f, "const ")?;
+        }
+        write!(f, "struct {} {{", self.type_name())?;
         self.newline(f, context)?;
 
         let mut byte_idx = 0;
@@ -116,7 +196,7 @@ impl StaticAllocation {
                 self.newline(f, context)?;
 
                 ptr_idx += 1;
-                cur_idx += StaticAllocation::ptr_size();
+                cur_idx += self.ptr_size;
             }
         }
 
@@ -147,7 +227,7 @@ impl StaticAllocation {
                 self.ptrs[ptr_idx].1.repr(f, context)?;
 
                 ptr_idx += 1;
-                cur_idx += StaticAllocation::ptr_size();
+                cur_idx += self.ptr_size;
             }
         }
 
@@ -168,3 +248,199 @@ impl Debug for StaticAllocation {
         self.default_repr(f)
     }
 }
+
+pub fn const_alloc_bytes(alloc: ConstAllocation) -> Vec<u8> {
+    let inner_alloc = alloc.inner();
+
+    inner_alloc
+        .inspect_with_uninit_and_ptr_outside_interpreter(0..inner_alloc.len())
+        .into()
+}
+
+/// Builds the initializers for all pointer slots of an allocation, keyed by their byte offset.
+/// The allocations they point to are emitted first, so they are defined before they are used
+pub fn alloc_relocations<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    alloc: ConstAllocation<'tcx>,
+) -> Vec<(usize, Expression)> {
+    let mut ptr_declrs: Vec<(usize, Expression)> = Vec::new();
+    let bytes = const_alloc_bytes(alloc);
+    let ptr_size = fn_cx.tcx.data_layout.pointer_size.bytes_usize();
+
+    for (offset, prov) in alloc.inner().provenance().ptrs().iter() {
+        let offset = offset.bytes_usize();
+        // the bytes of a pointer slot hold the offset into the allocation it points to
+        let target_offset = read_target_uint(
+            fn_cx.tcx.data_layout.endian,
+            &bytes[offset..offset + ptr_size],
+        )
+        .unwrap() as u64;
+
+        let reloc_target_alloc = fn_cx.tcx.global_alloc(prov.alloc_id());
+        debug!("Relocation at {} to {:?}", offset, reloc_target_alloc);
+
+        let target = match reloc_target_alloc {
+            GlobalAlloc::Function(finstance) => Expression::Constant {
+                value: format_fn_name(&fn_cx.tcx.symbol_name(finstance)),
+            },
+
+            GlobalAlloc::Memory(target_alloc) => {
+                let name = emit_memory_alloc(fn_cx, prov.alloc_id(), target_alloc);
+                address_of(name, target_offset)
+            }
+
+            GlobalAlloc::Static(def_id) => address_of(declare_static(fn_cx, def_id), target_offset),
+
+            // vtables are the same typed vtable structs trait object coercions point to
+            GlobalAlloc::VTable(ty, trait_ref) => {
+                let dyn_ty = vtable::dyn_type(fn_cx.tcx, trait_ref);
+                offset_by(vtable::vtable_ptr(fn_cx, ty, dyn_ty), target_offset)
+            }
+        };
+
+        ptr_declrs.push((offset, target));
+    }
+
+    ptr_declrs
+}
+
+/// Emits a memory allocation as a static allocation, if it wasn't emitted yet, and returns its name
+pub fn emit_memory_alloc<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    alloc_id: AllocId,
+    alloc: ConstAllocation<'tcx>,
+) -> String {
+    let alloc_name = format!("ALLOC_{}_CRATE_{}", alloc_id.0, fn_cx.crate_num);
+
+    if !fn_cx
+        .ongoing_codegen
+        .context
+        .exists_static_with_name(&alloc_name)
+    {
+        let ptr_declrs = alloc_relocations(fn_cx, alloc);
+        let ptr_size = fn_cx.tcx.data_layout.pointer_size.bytes_usize();
+        fn_cx
+            .ongoing_codegen
+            .context
+            .add_static(StaticAllocation::new(
+                alloc_name.clone(),
+                const_alloc_bytes(alloc),
+                ptr_declrs,
+                alloc.inner().align.bytes(),
+                ptr_size,
+            ));
+    }
+
+    alloc_name
+}
+
+// &name, `offset` bytes into the allocation
+fn address_of(name: String, offset: u64) -> Expression {
+    offset_by(
+        Expression::Constant {
+            value: format!("&{name}"),
+        },
+        offset,
+    )
+}
+
+/// `address`, or (int8_t*)address + offset for pointers into the middle of an allocation
+pub fn offset_by(address: Expression, offset: u64) -> Expression {
+    match offset {
+        0 => address,
+        _ => Expression::BinaryOp {
+            op: BinOpType::Add,
+            lhs: Box::new(Expression::Cast {
+                ty: CType::Pointer(Box::new(CType::Int(CIntTy::Int8))),
+                value: Box::new(address),
+            }),
+            rhs: Box::new(Expression::const_int(offset as i128)),
+        },
+    }
+}
+
+pub fn static_name<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> String {
+    format_fn_name(&tcx.symbol_name(Instance::mono(tcx, def_id)))
+}
+
+/// Makes sure a referenced static is declared before it is used, and returns its name
+pub fn declare_static<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    def_id: DefId,
+) -> String {
+    let name = static_name(fn_cx.tcx, def_id);
+
+    // statics of other crates and extern statics are defined elsewhere
+    if def_id.is_local() && !fn_cx.tcx.is_foreign_item(def_id) {
+        emit_static(fn_cx, def_id);
+    } else if !fn_cx.ongoing_codegen.context.exists_static_with_name(&name) {
+        fn_cx
+            .ongoing_codegen
+            .context
+            .add_static(StaticAllocation::new_extern(name.clone()));
+    }
+
+    name
+}
+
+/// Lowers a `static` item to a C global initialized with the evaluated static initializer.
+pub fn handle_static<'tcx, 'ccx>(
+    tcx: TyCtxt<'tcx>,
+    ongoing_codegen: &'ccx mut OngoingCodegen,
+    def_id: DefId,
+    rust_to_c_map: &'ccx mut std::collections::HashMap<Ty<'tcx>, CType>,
+    alloc_to_c_map: &'ccx mut std::collections::HashMap<AllocId, Expression>,
+) {
+    let mut fn_cx = CodegenFunctionCx::new_for_static(
+        tcx,
+        ongoing_codegen,
+        def_id,
+        rust_to_c_map,
+        alloc_to_c_map,
+        def_id.krate.as_usize(),
+    );
+    emit_static(&mut fn_cx, def_id);
+}
+
+// statics are emitted when first referenced, which might be before their own mono item
+fn emit_static<'tcx, 'ccx>(fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>, def_id: DefId) {
+    let tcx = fn_cx.tcx;
+    let name = static_name(tcx, def_id);
+    if fn_cx.ongoing_codegen.context.exists_static_with_name(&name) {
+        return;
+    }
+
+    let alloc = tcx
+        .eval_static_initializer(def_id)
+        .expect("Static initializer evaluation failed");
+    let ty = tcx.type_of(def_id).instantiate_identity();
+
+    debug!("Static {:?} of type {:?}", def_id, ty);
+
+    // an empty placeholder stops the recursion of statics pointing to each other,
+    // in C they see each other through the forward declarations
+    fn_cx
+        .ongoing_codegen
+        .context
+        .add_static(StaticAllocation::new_extern(name.clone()));
+    let ptr_declrs = alloc_relocations(fn_cx, alloc);
+    fn_cx
+        .ongoing_codegen
+        .context
+        .get_mut_statics()
+        .retain(|s| s.name != name);
+
+    let mut static_alloc = StaticAllocation::new(
+        name,
+        const_alloc_bytes(alloc),
+        ptr_declrs,
+        alloc.inner().align.bytes(),
+        tcx.data_layout.pointer_size.bytes_usize(),
+    );
+
+    // statics with interior mutability can be written to even without `static mut`
+    static_alloc
+        .set_const(!tcx.is_mutable_static(def_id) && ty.is_freeze(tcx, ParamEnv::reveal_all()));
+
+    fn_cx.ongoing_codegen.context.add_static(static_alloc);
+}
@@ -0,0 +1,651 @@
+use std::fmt::{self, Debug};
+
+use rustc_ast::ast::{InlineAsmOptions, InlineAsmTemplatePiece};
+use rustc_codegen_ssa::common::asm_const_to_str;
+use rustc_hir::{InlineAsmOperand, ItemId, ItemKind};
+use rustc_middle::mir;
+use rustc_middle::ty::{Instance, ParamEnv, TyCtxt, TyKind};
+use rustc_target::asm::{
+    AArch64InlineAsmRegClass, InlineAsmArch, InlineAsmRegClass, InlineAsmRegOrRegClass,
+    RiscVInlineAsmRegClass, X86InlineAsmRegClass,
+};
+use tracing::debug;
+
+use crate::{
+    alloc,
+    base::OngoingCodegen,
+    bb::BasicBlockIdentifier,
+    crepr::{indent, Representable, RepresentationContext},
+    definition::CVarDef,
+    expression::Expression,
+    function::{format_fn_name, CodegenFunctionCx},
+    stmt::{handle_operand, handle_place, Statement},
+    ty::{CType, CUIntTy},
+    utils::escape_c_string,
+};
+
+// gcc and clang default to AT&T syntax, while rust defaults to intel syntax on x86
+const INTEL_SYNTAX: &str = ".intel_syntax noprefix";
+const ATT_SYNTAX: &str = ".att_syntax";
+
+/// A file-scope `__asm__` block, produced from a `global_asm!` item
+#[derive(Clone, PartialEq, Eq)]
+pub struct CGlobalAsm {
+    template: String,
+    intel_syntax: bool,
+}
+
+impl CGlobalAsm {
+    pub fn new(template: String, intel_syntax: bool) -> Self {
+        Self {
+            template,
+            intel_syntax,
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+
+        if self.intel_syntax {
+            lines.push(INTEL_SYNTAX.to_string());
+        }
+
+        lines.extend(self.template.lines().map(|line| line.to_string()));
+
+        if self.intel_syntax {
+            lines.push(ATT_SYNTAX.to_string());
+        }
+
+        lines
+    }
+}
+
+impl Representable for CGlobalAsm {
+    fn repr(&self, f: &mut (dyn fmt::Write), context: &mut RepresentationContext) -> fmt::Result {
+        write!(f, "__asm__(")?;
+        self.newline(f, context)?;
+
+        for line in self.lines() {
+            indent(f, context)?;
+            write!(f, "\"{}\\n\"", escape_c_string(&line))?;
+            self.newline(f, context)?;
+        }
+
+        write!(f, ");")
+    }
+}
+
+impl Debug for CGlobalAsm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.default_repr(f)
+    }
+}
+
+pub fn is_x86<'tcx>(tcx: TyCtxt<'tcx>) -> bool {
+    matches!(
+        tcx.sess.asm_arch,
+        Some(InlineAsmArch::X86 | InlineAsmArch::X86_64)
+    )
+}
+
+pub fn handle_global_asm<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ongoing_codegen: &mut OngoingCodegen,
+    item_id: ItemId,
+) {
+    let item = tcx.hir().item(item_id);
+
+    let ItemKind::GlobalAsm(asm) = item.kind else {
+        panic!("Expected global asm item, got {:?}", item.kind);
+    };
+
+    debug!("Global asm: {:?}", asm);
+
+    let mut template = String::new();
+
+    for piece in asm.template {
+        match *piece {
+            InlineAsmTemplatePiece::String(ref s) => template.push_str(s),
+
+            InlineAsmTemplatePiece::Placeholder {
+                operand_idx,
+                modifier: _,
+                span,
+            } => match asm.operands[operand_idx].0 {
+                InlineAsmOperand::Const { ref anon_const } => {
+                    let const_value = tcx
+                        .const_eval_poly(anon_const.def_id.to_def_id())
+                        .expect("Global asm const operand evaluation failed");
+                    let ty = tcx
+                        .typeck_body(anon_const.body)
+                        .node_type(anon_const.hir_id);
+                    let layout = tcx.layout_of(ParamEnv::reveal_all().and(ty)).unwrap();
+
+                    template.push_str(&asm_const_to_str(tcx, span, const_value, layout));
+                }
+
+                InlineAsmOperand::SymFn { ref anon_const } => {
+                    let ty = tcx
+                        .typeck_body(anon_const.body)
+                        .node_type(anon_const.hir_id);
+
+                    let instance = match ty.kind() {
+                        &TyKind::FnDef(def_id, args) => Instance::new(def_id, args),
+                        _ => panic!("Global asm sym operand is not a function: {:?}", ty),
+                    };
+
+                    template.push_str(&format_fn_name(&tcx.symbol_name(instance)));
+                }
+
+                InlineAsmOperand::SymStatic { path: _, def_id } => {
+                    template.push_str(&alloc::static_name(tcx, def_id));
+                }
+
+                ref operand => {
+                    panic!("Invalid operand for global asm: {:?}", operand);
+                }
+            },
+        }
+    }
+
+    let intel_syntax = is_x86(tcx) && !asm.options.contains(InlineAsmOptions::ATT_SYNTAX);
+
+    ongoing_codegen
+        .context
+        .add_global_asm(CGlobalAsm::new(template, intel_syntax));
+}
+
+/// An operand of an extended asm statement, e.g. `"=r"(var1)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CAsmOperand {
+    constraint: String,
+    value: Expression,
+}
+
+impl Representable for CAsmOperand {
+    fn repr(&self, f: &mut (dyn fmt::Write), context: &mut RepresentationContext) -> fmt::Result {
+        write!(f, "\"{}\"(", self.constraint)?;
+        self.value.repr(f, context)?;
+        write!(f, ")")
+    }
+}
+
+/// A variable declared around the asm statement, either pinned to an explicit
+/// register or used as a scratch location for discarded outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CAsmLocal {
+    var: CVarDef,
+    register: Option<String>,
+    init: Option<Expression>,
+}
+
+/// An `asm!` block lowered to a GCC extended asm statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CInlineAsm {
+    template: String,
+    outputs: Vec<CAsmOperand>,
+    inputs: Vec<CAsmOperand>,
+    clobbers: Vec<String>,
+    labels: Vec<BasicBlockIdentifier>,
+    volatile: bool,
+    locals: Vec<CAsmLocal>,
+    // outputs living in register variables are copied back to their places after the asm
+    writebacks: Vec<(Expression, Expression)>,
+}
+
+impl CInlineAsm {
+    fn new(volatile: bool) -> Self {
+        Self {
+            template: String::new(),
+            outputs: Vec::new(),
+            inputs: Vec::new(),
+            clobbers: Vec::new(),
+            labels: Vec::new(),
+            volatile,
+            locals: Vec::new(),
+            writebacks: Vec::new(),
+        }
+    }
+
+    fn add_local(
+        &mut self,
+        ty: CType,
+        register: Option<&str>,
+        init: Option<Expression>,
+    ) -> Expression {
+        let id = self.locals.len();
+        let name = format!("__asm_{}", id);
+
+        self.locals.push(CAsmLocal {
+            var: CVarDef::new(id, name.clone(), ty),
+            register: register.map(|reg| reg.to_string()),
+            init,
+        });
+
+        Expression::Constant { value: name }
+    }
+
+    fn add_output(&mut self, constraint: String, value: Expression) -> usize {
+        self.outputs.push(CAsmOperand { constraint, value });
+        self.outputs.len() - 1
+    }
+
+    fn add_input(&mut self, constraint: String, value: Expression) -> usize {
+        self.inputs.push(CAsmOperand { constraint, value });
+        self.inputs.len() - 1
+    }
+}
+
+fn repr_list<T: Representable>(
+    items: &[T],
+    f: &mut (dyn fmt::Write),
+    context: &mut RepresentationContext,
+) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        item.repr(f, context)?;
+    }
+    Ok(())
+}
+
+impl Representable for CInlineAsm {
+    fn repr(&self, f: &mut (dyn fmt::Write), context: &mut RepresentationContext) -> fmt::Result {
+        let needs_block = !self.locals.is_empty() || !self.writebacks.is_empty();
+
+        if needs_block {
+            write!(f, "{{ ")?;
+            for local in &self.locals {
+                if local.register.is_some() {
+                    write!(f, "register ")?;
+                }
+                local.var.repr(f, context)?;
+                if let Some(register) = &local.register {
+                    write!(f, " __asm__(\"{}\")", register)?;
+                }
+                if let Some(init) = &local.init {
+                    write!(f, " = ")?;
+                    init.repr(f, context)?;
+                }
+                write!(f, "; ")?;
+            }
+        }
+
+        write!(f, "__asm__ ")?;
+        if self.volatile {
+            write!(f, "volatile ")?;
+        }
+        if !self.labels.is_empty() {
+            write!(f, "goto ")?;
+        }
+
+        write!(
+            f,
+            "(\"{}\" : ",
+            self.template
+                .split('\n')
+                .map(escape_c_string)
+                .collect::<Vec<_>>()
+                .join("\\n\\t")
+        )?;
+        repr_list(&self.outputs, f, context)?;
+        write!(f, " : ")?;
+        repr_list(&self.inputs, f, context)?;
+        write!(f, " : ")?;
+        for (i, clobber) in self.clobbers.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "\"{}\"", clobber)?;
+        }
+        if !self.labels.is_empty() {
+            write!(f, " : ")?;
+            repr_list(&self.labels, f, context)?;
+        }
+        write!(f, ")")?;
+
+        if needs_block {
+            write!(f, "; ")?;
+            for (place, local) in &self.writebacks {
+                place.repr(f, context)?;
+                write!(f, " = ")?;
+                local.repr(f, context)?;
+                write!(f, "; ")?;
+            }
+            write!(f, "}}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How a rust operand is referenced from the asm template
+#[derive(Clone)]
+enum AsmOperandRef {
+    Output(usize, InlineAsmRegClass),
+    Input(usize, InlineAsmRegClass),
+    Text(String),
+}
+
+/// How an operand register is passed to gcc
+enum RegConstraint {
+    Constraint(String),
+    // registers without a constraint letter are pinned with a local register variable
+    Register(&'static str),
+}
+
+fn class_constraint(class: InlineAsmRegClass) -> &'static str {
+    match class {
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::reg) => "r",
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::reg_abcd) => "Q",
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::reg_byte) => "q",
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::xmm_reg)
+        | InlineAsmRegClass::X86(X86InlineAsmRegClass::ymm_reg) => "x",
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::zmm_reg) => "v",
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::kreg) => "Yk",
+        InlineAsmRegClass::AArch64(AArch64InlineAsmRegClass::reg) => "r",
+        InlineAsmRegClass::AArch64(AArch64InlineAsmRegClass::vreg) => "w",
+        InlineAsmRegClass::AArch64(AArch64InlineAsmRegClass::vreg_low16) => "x",
+        InlineAsmRegClass::RiscV(RiscVInlineAsmRegClass::reg) => "r",
+        InlineAsmRegClass::RiscV(RiscVInlineAsmRegClass::freg) => "f",
+        _ => panic!("Unsupported asm register class: {:?}", class),
+    }
+}
+
+fn reg_constraint<'tcx>(tcx: TyCtxt<'tcx>, reg: InlineAsmRegOrRegClass) -> RegConstraint {
+    match reg {
+        InlineAsmRegOrRegClass::Reg(reg) => {
+            if is_x86(tcx) {
+                let constraint = match reg.name() {
+                    "ax" => Some("a"),
+                    "bx" => Some("b"),
+                    "cx" => Some("c"),
+                    "dx" => Some("d"),
+                    "si" => Some("S"),
+                    "di" => Some("D"),
+                    _ => None,
+                };
+
+                if let Some(constraint) = constraint {
+                    return RegConstraint::Constraint(constraint.to_string());
+                }
+            }
+
+            RegConstraint::Register(reg.name())
+        }
+        InlineAsmRegOrRegClass::RegClass(class) => {
+            RegConstraint::Constraint(class_constraint(class).to_string())
+        }
+    }
+}
+
+/// Maps a rust template modifier to the gcc operand modifier
+fn gcc_modifier<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    class: InlineAsmRegClass,
+    modifier: Option<char>,
+) -> Option<char> {
+    match class {
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::reg | X86InlineAsmRegClass::reg_abcd) => {
+            match modifier {
+                None if tcx.sess.asm_arch == Some(InlineAsmArch::X86_64) => Some('q'),
+                None => Some('k'),
+                Some('l') => Some('b'),
+                Some('h') => Some('h'),
+                Some('x') => Some('w'),
+                Some('e') => Some('k'),
+                Some('r') => Some('q'),
+                Some(m) => panic!("Invalid x86 asm modifier: {}", m),
+            }
+        }
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::reg_byte) => None,
+        InlineAsmRegClass::X86(
+            X86InlineAsmRegClass::xmm_reg
+            | X86InlineAsmRegClass::ymm_reg
+            | X86InlineAsmRegClass::zmm_reg,
+        ) => match (class, modifier) {
+            (_, Some('x')) | (InlineAsmRegClass::X86(X86InlineAsmRegClass::xmm_reg), None) => {
+                Some('x')
+            }
+            (_, Some('y')) | (InlineAsmRegClass::X86(X86InlineAsmRegClass::ymm_reg), None) => {
+                Some('t')
+            }
+            (_, Some('z')) | (InlineAsmRegClass::X86(X86InlineAsmRegClass::zmm_reg), None) => {
+                Some('g')
+            }
+            (_, Some(m)) => panic!("Invalid x86 vector asm modifier: {}", m),
+            _ => unreachable!(),
+        },
+        _ => modifier,
+    }
+}
+
+// % starts an operand in gcc templates, and on x86 {, | and } separate the alternatives of asm dialects
+fn escape_template<'tcx>(tcx: TyCtxt<'tcx>, s: &str) -> String {
+    let s = s.replace('%', "%%");
+    if !is_x86(tcx) {
+        return s;
+    }
+
+    s.replace('{', "%{").replace('|', "%|").replace('}', "%}")
+}
+
+pub fn handle_inline_asm<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    template: &[InlineAsmTemplatePiece],
+    operands: &[mir::InlineAsmOperand<'tcx>],
+    options: InlineAsmOptions,
+    targets: &[mir::BasicBlock],
+) -> Vec<Statement> {
+    let tcx = fn_cx.tcx;
+    let mut asm = CInlineAsm::new(!options.contains(InlineAsmOptions::PURE));
+    let mut operand_refs: Vec<Option<AsmOperandRef>> = vec![None; operands.len()];
+
+    let scratch_ty = CType::UInt(CUIntTy::from(tcx.data_layout.pointer_size.bits()));
+
+    // gcc numbers the outputs before the inputs, so they are collected first
+    for (idx, operand) in operands.iter().enumerate() {
+        let (reg, late, out_place, in_value) = match operand {
+            mir::InlineAsmOperand::Out { reg, late, place } => (*reg, *late, *place, None),
+            mir::InlineAsmOperand::InOut {
+                reg,
+                late,
+                in_value,
+                out_place,
+            } => (*reg, *late, *out_place, Some(in_value)),
+            _ => continue,
+        };
+
+        let early = if late { "" } else { "&" };
+
+        match reg_constraint(tcx, reg) {
+            RegConstraint::Register(name) if out_place.is_none() && in_value.is_none() => {
+                match name {
+                    "st(0)" => asm.clobbers.push("st".to_string()),
+                    // gcc doesn't know the AMX tile registers
+                    name if name.starts_with("tmm") => {}
+                    name => asm.clobbers.push(name.to_string()),
+                }
+            }
+
+            RegConstraint::Register(name) => {
+                let ty = match (out_place, in_value) {
+                    (Some(place), _) => place.ty(&fn_cx.mir.local_decls, tcx).ty,
+                    (None, Some(value)) => value.ty(&fn_cx.mir.local_decls, tcx),
+                    _ => unreachable!(),
+                };
+                let ty = fn_cx.monomorphize(ty);
+                let ctype = fn_cx.rust_to_c_type(&ty);
+
+                let init = in_value.map(|value| handle_operand(fn_cx, value));
+                let local = asm.add_local(ctype, Some(name), init);
+
+                let modifier = if in_value.is_some() { "+" } else { "=" };
+                let constraint =
+                    format!("{}{}{}", modifier, early, class_constraint(reg.reg_class()));
+                let out_idx = asm.add_output(constraint, local.clone());
+                operand_refs[idx] = Some(AsmOperandRef::Output(out_idx, reg.reg_class()));
+
+                if let Some(place) = out_place {
+                    let place = handle_place(fn_cx, &place);
+                    asm.writebacks.push((place, local));
+                }
+            }
+
+            RegConstraint::Constraint(constraint) => {
+                let value = match out_place {
+                    Some(place) => handle_place(fn_cx, &place),
+                    None => asm.add_local(scratch_ty.clone(), None, None),
+                };
+
+                let constraint = format!("={}{}", early, constraint);
+                let out_idx = asm.add_output(constraint, value);
+                operand_refs[idx] = Some(AsmOperandRef::Output(out_idx, reg.reg_class()));
+            }
+        }
+    }
+
+    for (idx, operand) in operands.iter().enumerate() {
+        match operand {
+            mir::InlineAsmOperand::In { reg, value } => {
+                let expr = handle_operand(fn_cx, value);
+
+                let constraint = match reg_constraint(tcx, *reg) {
+                    RegConstraint::Constraint(constraint) => constraint,
+                    RegConstraint::Register(name) => {
+                        let ty = fn_cx.monomorphize(value.ty(&fn_cx.mir.local_decls, tcx));
+                        let ctype = fn_cx.rust_to_c_type(&ty);
+                        let local = asm.add_local(ctype, Some(name), Some(expr));
+
+                        let in_idx =
+                            asm.add_input(class_constraint(reg.reg_class()).to_string(), local);
+                        operand_refs[idx] = Some(AsmOperandRef::Input(in_idx, reg.reg_class()));
+                        continue;
+                    }
+                };
+
+                let in_idx = asm.add_input(constraint, expr);
+                operand_refs[idx] = Some(AsmOperandRef::Input(in_idx, reg.reg_class()));
+            }
+
+            mir::InlineAsmOperand::InOut { in_value, .. } => {
+                // explicit registers are already read and written through a "+" output
+                let Some(AsmOperandRef::Output(out_idx, _)) = operand_refs[idx] else {
+                    unreachable!()
+                };
+                if asm.outputs[out_idx].constraint.starts_with('+') {
+                    continue;
+                }
+
+                let expr = handle_operand(fn_cx, in_value);
+                asm.add_input(out_idx.to_string(), expr);
+            }
+
+            mir::InlineAsmOperand::Const { value } => {
+                let const_ = fn_cx.monomorphize(value.const_);
+                let const_value = const_
+                    .eval(tcx, ParamEnv::reveal_all(), value.span)
+                    .expect("Asm const operand evaluation failed");
+                let ty = fn_cx.monomorphize(value.ty());
+                let layout = tcx.layout_of(ParamEnv::reveal_all().and(ty)).unwrap();
+
+                operand_refs[idx] = Some(AsmOperandRef::Text(asm_const_to_str(
+                    tcx,
+                    value.span,
+                    const_value,
+                    layout,
+                )));
+            }
+
+            mir::InlineAsmOperand::SymFn { value } => {
+                let ty = fn_cx.monomorphize(value.ty());
+
+                let instance = match ty.kind() {
+                    &TyKind::FnDef(def_id, args) => {
+                        Instance::resolve_for_fn_ptr(tcx, ParamEnv::reveal_all(), def_id, args)
+                            .unwrap()
+                    }
+                    _ => panic!("Asm sym operand is not a function: {:?}", ty),
+                };
+
+                operand_refs[idx] = Some(AsmOperandRef::Text(format_fn_name(
+                    &tcx.symbol_name(instance),
+                )));
+            }
+
+            mir::InlineAsmOperand::SymStatic { def_id } => {
+                operand_refs[idx] = Some(AsmOperandRef::Text(alloc::static_name(tcx, *def_id)));
+            }
+
+            mir::InlineAsmOperand::Label { target_index } => {
+                let label = BasicBlockIdentifier(targets[*target_index].as_usize());
+                operand_refs[idx] = Some(AsmOperandRef::Text(format!("%l[bb{}]", label.0)));
+                asm.labels.push(label);
+            }
+
+            mir::InlineAsmOperand::Out { .. } => {}
+        }
+    }
+
+    let mut template_str = String::new();
+    for piece in template {
+        match piece {
+            InlineAsmTemplatePiece::String(s) => template_str.push_str(&escape_template(tcx, s)),
+
+            InlineAsmTemplatePiece::Placeholder {
+                operand_idx,
+                modifier,
+                span: _,
+            } => {
+                let (gcc_idx, class) = match &operand_refs[*operand_idx] {
+                    Some(AsmOperandRef::Output(idx, class)) => (*idx, *class),
+                    Some(AsmOperandRef::Input(idx, class)) => (asm.outputs.len() + idx, *class),
+                    Some(AsmOperandRef::Text(text)) => {
+                        template_str.push_str(text);
+                        continue;
+                    }
+                    None => panic!("Asm placeholder refers to an unreferencable operand"),
+                };
+
+                template_str.push('%');
+                if let Some(modifier) = gcc_modifier(tcx, class, *modifier) {
+                    template_str.push(modifier);
+                }
+                template_str.push_str(&gcc_idx.to_string());
+            }
+        }
+    }
+
+    if is_x86(tcx) && !options.contains(InlineAsmOptions::ATT_SYNTAX) {
+        asm.template = format!("{INTEL_SYNTAX}\n{template_str}\n{ATT_SYNTAX}");
+    } else {
+        asm.template = template_str;
+    }
+
+    if !options.contains(InlineAsmOptions::NOMEM) {
+        asm.clobbers.push("memory".to_string());
+    }
+    if is_x86(tcx) && !options.contains(InlineAsmOptions::PRESERVES_FLAGS) {
+        asm.clobbers.push("cc".to_string());
+    }
+
+    let mut stmts = vec![Statement::from_expression(Expression::InlineAsm {
+        asm: Box::new(asm),
+    })];
+
+    if options.contains(InlineAsmOptions::NORETURN) {
+        stmts.push(Statement::from_expression(Expression::FnCall {
+            function: Box::new(Expression::Constant {
+                value: "__builtin_unreachable".to_string(),
+            }),
+            args: vec![],
+        }));
+    } else if let Some(target) = targets.first() {
+        stmts.push(Statement::from_expression(Expression::Goto {
+            target: BasicBlockIdentifier(target.as_usize()),
+        }));
+    }
+
+    stmts
+}
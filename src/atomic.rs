@@ -0,0 +1,316 @@
+use rustc_middle::mir::Operand;
+use rustc_middle::ty::{Instance, ParamEnv, Ty};
+use rustc_span::source_map::Spanned;
+use tracing::{debug, debug_span};
+
+use crate::{
+    bb::{BasicBlock, BasicBlockIdentifier},
+    definition::{CVarDecl, CVarDef},
+    expression::{Expression, UnaryOpType, VariableAccess},
+    function::{CFunction, CodegenFunctionCx},
+    stmt::{handle_operand, Statement},
+    ty::{CType, CUIntTy},
+};
+
+fn call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FnCall {
+        function: Expression::constant(&function.to_string()),
+        args,
+    }
+}
+
+fn memory_order_ty() -> CType {
+    CType::Opaque("memory_order".to_string())
+}
+
+/// The C11 memory order of a Rust ordering, as spelled in the intrinsic names
+fn memory_order(ordering: &str) -> Expression {
+    let order = match ordering {
+        // C has no weaker ordering than relaxed
+        "relaxed" | "unordered" => "memory_order_relaxed",
+        "acquire" => "memory_order_acquire",
+        "release" => "memory_order_release",
+        "acqrel" => "memory_order_acq_rel",
+        "seqcst" => "memory_order_seq_cst",
+        _ => panic!("Unknown atomic ordering: {}", ordering),
+    };
+
+    Expression::Constant {
+        value: order.to_string(),
+    }
+}
+
+/// Type the atomic operation is done on, atomic pointers are handled as integers of the same size
+fn value_ty<'tcx, 'ccx>(fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>, ty: &Ty<'tcx>) -> CType {
+    match fn_cx.rust_to_c_type(ty) {
+        CType::Pointer(_) => CType::UInt(CUIntTy::from(fn_cx.tcx.data_layout.pointer_size.bits())),
+        c_ty => c_ty,
+    }
+}
+
+fn atomic_ptr_ty(value_ty: &CType) -> CType {
+    CType::Pointer(Box::new(CType::Atomic(Box::new(value_ty.clone()))))
+}
+
+fn cast(ty: &CType, value: Expression) -> Expression {
+    Expression::Cast {
+        ty: ty.clone(),
+        value: Box::new(value),
+    }
+}
+
+/// Lowers the `atomic_*` intrinsics to the `atomic_*_explicit` functions of stdatomic.h,
+/// `op` is the name of the intrinsic without the `atomic_` prefix, e.g. `load_acquire`
+pub fn handle_atomic_intrinsic<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    instance: Instance<'tcx>,
+    op: &str,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+    destination: Expression,
+) -> Statement {
+    let _span = debug_span!("handle_atomic_intrinsic").entered();
+    debug!("Atomic intrinsic {} with args {:?}", op, instance.args);
+
+    let (op, orderings) = op.split_once('_').unwrap_or((op, ""));
+    let orderings = orderings
+        .split('_')
+        .map(memory_order)
+        .collect::<Vec<Expression>>();
+
+    match op {
+        "fence" => return Statement::from_expression(call("atomic_thread_fence", orderings)),
+        "singlethreadfence" => {
+            return Statement::from_expression(call("atomic_signal_fence", orderings))
+        }
+        _ => {}
+    }
+
+    let rust_ty = instance.args.type_at(0);
+    let ty = fn_cx.rust_to_c_type(&rust_ty);
+    let value_ty = value_ty(fn_cx, &rust_ty);
+    let is_ptr = ty != value_ty;
+
+    let mut args = args
+        .iter()
+        .map(|arg| handle_operand(fn_cx, &arg.node))
+        .collect::<Vec<Expression>>();
+
+    // the first argument is always the pointer to the atomic value
+    args[0] = cast(&atomic_ptr_ty(&value_ty), args[0].clone());
+    if is_ptr {
+        for arg in args.iter_mut().skip(1) {
+            *arg = cast(&value_ty, arg.clone());
+        }
+    }
+    args.extend(orderings);
+
+    let value = match op {
+        "store" => return Statement::from_expression(call("atomic_store_explicit", args)),
+        "load" => call("atomic_load_explicit", args),
+        "xchg" => call("atomic_exchange_explicit", args),
+        "xadd" => call("atomic_fetch_add_explicit", args),
+        "xsub" => call("atomic_fetch_sub_explicit", args),
+        "and" => call("atomic_fetch_and_explicit", args),
+        "or" => call("atomic_fetch_or_explicit", args),
+        "xor" => call("atomic_fetch_xor_explicit", args),
+
+        // operations without a stdatomic.h function are compare-exchange loops
+        "nand" | "max" | "min" | "umax" | "umin" => {
+            let c_fn = fetch_update_fn(op, &value_ty);
+            let name = c_fn.get_name().to_string();
+            if !fn_cx
+                .ongoing_codegen
+                .context
+                .exists_header_fn_with_name(&name)
+            {
+                fn_cx
+                    .ongoing_codegen
+                    .context
+                    .get_mut_header_functions()
+                    .push(c_fn);
+            }
+            call(&name, args)
+        }
+
+        // returns the (previous value, success) tuple
+        "cxchg" | "cxchgweak" => {
+            let sig = instance
+                .ty(fn_cx.tcx, ParamEnv::reveal_all())
+                .fn_sig(fn_cx.tcx);
+            let sig = fn_cx
+                .tcx
+                .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), sig);
+            let tuple_ty = fn_cx.rust_to_c_type(&sig.output());
+
+            let c_fn = compare_exchange_fn(op == "cxchgweak", &value_ty, &ty, tuple_ty);
+            let name = c_fn.get_name().to_string();
+            if !fn_cx
+                .ongoing_codegen
+                .context
+                .exists_header_fn_with_name(&name)
+            {
+                fn_cx
+                    .ongoing_codegen
+                    .context
+                    .get_mut_header_functions()
+                    .push(c_fn);
+            }
+            return Statement::from_expression(destination.assign(Box::new(call(&name, args))));
+        }
+
+        _ => panic!("Unknown atomic intrinsic: atomic_{}", op),
+    };
+
+    let value = if is_ptr { cast(&ty, value) } else { value };
+    Statement::from_expression(destination.assign(Box::new(value)))
+}
+
+// e.g. uint8_t* becomes uint8_t_, so the type can be part of a function name
+fn type_name(ty: &CType) -> String {
+    format!("{:?}", ty)
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+// T codegenc_atomic_{op}_T(_Atomic T* dst, T val, memory_order order) {
+//     T old = atomic_load_explicit(dst, memory_order_relaxed);
+//     T new;
+// bb0:
+//     new = ~(old & val); or new = old; if (val > old) new = val;
+//     if (!atomic_compare_exchange_weak_explicit(dst, &old, new, order, memory_order_relaxed)) goto bb0;
+// bb1:
+//     return old;
+// }
+fn fetch_update_fn(op: &str, value_ty: &CType) -> CFunction {
+    let mut c_fn = CFunction::new(
+        format!("codegenc_atomic_{op}_{}", type_name(value_ty)),
+        value_ty.clone(),
+    );
+    c_fn.add_signature_var(CVarDef::new(0, "dst".to_string(), atomic_ptr_ty(value_ty)));
+    c_fn.add_signature_var(CVarDef::new(1, "val".to_string(), value_ty.clone()));
+    c_fn.add_signature_var(CVarDef::new(2, "order".to_string(), memory_order_ty()));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(3, "old".to_string(), value_ty.clone()),
+        Some(Box::new(call(
+            "atomic_load_explicit",
+            vec![Expression::unbvari(0), memory_order("relaxed")],
+        ))),
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(4, "new".to_string(), value_ty.clone()),
+        None,
+    ));
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    let new = Expression::unbvari(4);
+    match op {
+        "nand" => {
+            bb0.push(Statement::from_expression(new.assign(Box::new(
+                Expression::UnaryOp {
+                    op: UnaryOpType::BitNot,
+                    val: Expression::vari(3) & Expression::vari(1),
+                },
+            ))));
+        }
+        _ => {
+            let replace = match op {
+                "max" | "umax" => Expression::vari(1).gt(Expression::vari(3)),
+                _ => Expression::vari(1).lt(Expression::vari(3)),
+            };
+            bb0.push(Statement::from_expression(new.assign(Expression::vari(3))));
+            bb0.push(Statement::from_expression(Expression::If {
+                cond: replace,
+                then: Box::new(new.assign(Expression::vari(1))),
+            }));
+        }
+    }
+
+    let exchanged = call(
+        "atomic_compare_exchange_weak_explicit",
+        vec![
+            Expression::unbvari(0),
+            Expression::Variable {
+                local: 3,
+                access: vec![VariableAccess::Reference],
+            },
+            Expression::unbvari(4),
+            Expression::unbvari(2),
+            memory_order("relaxed"),
+        ],
+    );
+    bb0.push(Statement::from_expression(Expression::SwitchJump {
+        value: Box::new(exchanged),
+        cases: vec![(Box::new(Expression::const_int(0)), BasicBlockIdentifier(0))],
+        default: BasicBlockIdentifier(1),
+    }));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(Statement::from_expression(Expression::Return {
+        value: Expression::vari(3),
+    }));
+
+    c_fn.push_bb(bb0);
+    c_fn.push_bb(bb1);
+    c_fn
+}
+
+// (T, bool) codegenc_atomic_cxchg_{tuple}(_Atomic T* dst, T old, T src, memory_order success, memory_order failure) {
+//     bool ok = atomic_compare_exchange_strong_explicit(dst, &old, src, success, failure);
+//     return ((T, bool)){ old, ok };
+// }
+fn compare_exchange_fn(weak: bool, value_ty: &CType, ty: &CType, tuple_ty: CType) -> CFunction {
+    let tuple_info = tuple_ty.as_composite_info();
+    let (op, cas) = if weak {
+        ("cxchgweak", "atomic_compare_exchange_weak_explicit")
+    } else {
+        ("cxchg", "atomic_compare_exchange_strong_explicit")
+    };
+
+    let mut c_fn = CFunction::new(
+        format!("codegenc_atomic_{op}_{}", tuple_info.name),
+        tuple_ty,
+    );
+    c_fn.add_signature_var(CVarDef::new(0, "dst".to_string(), atomic_ptr_ty(value_ty)));
+    c_fn.add_signature_var(CVarDef::new(1, "old".to_string(), value_ty.clone()));
+    c_fn.add_signature_var(CVarDef::new(2, "src".to_string(), value_ty.clone()));
+    c_fn.add_signature_var(CVarDef::new(3, "success".to_string(), memory_order_ty()));
+    c_fn.add_signature_var(CVarDef::new(4, "failure".to_string(), memory_order_ty()));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(5, "ok".to_string(), CType::Bool),
+        Some(Box::new(call(
+            cas,
+            vec![
+                Expression::unbvari(0),
+                Expression::Variable {
+                    local: 1,
+                    access: vec![VariableAccess::Reference],
+                },
+                Expression::unbvari(2),
+                Expression::unbvari(3),
+                Expression::unbvari(4),
+            ],
+        ))),
+    ));
+
+    // old holds the previous value whether the exchange succeeded or not
+    let previous = if ty != value_ty {
+        cast(ty, Expression::unbvari(1))
+    } else {
+        Expression::unbvari(1)
+    };
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(Statement::from_expression(Expression::Return {
+        value: Expression::strct(
+            Expression::constant(&tuple_info.name),
+            vec![previous, Expression::unbvari(5)],
+        ),
+    }));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
@@ -22,12 +22,15 @@ use rustc_metadata::EncodedMetadata;
 use rustc_middle::mir::interpret::AllocId;
 use rustc_middle::mir::mono::{CodegenUnit, MonoItem};
 use rustc_middle::ty::print::with_no_trimmed_paths;
+use rustc_middle::ty::{Instance, InstanceDef};
 use rustc_session::config::{OutputFilenames, OutputType};
 use tracing::debug;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
 use crate::alloc;
+use crate::asm;
+use crate::definition;
 use crate::expression::Expression;
 use crate::function;
 use crate::header;
@@ -39,24 +42,34 @@ use crate::write;
 
 pub struct Context {
     includes: Vec<include::Include>,
+    global_asm: Vec<asm::CGlobalAsm>,
     header_includes: Vec<include::Include>,
+    // feature test macros, they change what the includes declare
+    feature_macros: Vec<header::CDefine>,
     defines: Vec<header::CDefine>,
     functions: Vec<function::CFunction>,
     header_functions: Vec<function::CFunction>,
     structs: Vec<structure::CComposite>,
     statics: Vec<alloc::StaticAllocation>,
+    globals: Vec<definition::CVarDecl>,
+    // initialized with function pointers, so they come after the prototypes
+    vtables: Vec<definition::CVarDecl>,
 }
 
 impl Context {
     pub fn new() -> Self {
         Self {
             includes: Vec::new(),
+            global_asm: Vec::new(),
             header_includes: Vec::new(),
+            feature_macros: Vec::new(),
             defines: Vec::new(),
             functions: Vec::new(),
             header_functions: Vec::new(),
             structs: Vec::new(),
             statics: Vec::new(),
+            globals: Vec::new(),
+            vtables: Vec::new(),
         }
     }
 
@@ -74,6 +87,12 @@ impl Context {
     pub fn get_mut_h_includes(&mut self) -> &mut Vec<include::Include> {
         &mut self.header_includes
     }
+    pub fn get_feature_macros(&self) -> &Vec<header::CDefine> {
+        &self.feature_macros
+    }
+    pub fn get_mut_feature_macros(&mut self) -> &mut Vec<header::CDefine> {
+        &mut self.feature_macros
+    }
     pub fn get_defines(&self) -> &Vec<header::CDefine> {
         &self.defines
     }
@@ -105,6 +124,10 @@ impl Context {
         &mut self.structs
     }
 
+    pub fn exists_fn_with_name(&self, name: &str) -> bool {
+        self.get_functions().iter().any(|f| f.get_name() == name)
+    }
+
     pub fn exists_header_fn_with_name(&self, name: &str) -> bool {
         for f in self.get_header_functions() {
             if f.get_name() == name {
@@ -171,6 +194,38 @@ impl Context {
     pub fn get_statics(&self) -> &Vec<alloc::StaticAllocation> {
         &self.statics
     }
+
+    pub fn get_mut_statics(&mut self) -> &mut Vec<alloc::StaticAllocation> {
+        &mut self.statics
+    }
+
+    pub fn exists_static_with_name(&self, name: &str) -> bool {
+        self.get_statics().iter().any(|s| s.get_name() == name)
+    }
+
+    pub fn add_global(&mut self, global: definition::CVarDecl) {
+        self.globals.push(global);
+    }
+
+    pub fn get_globals(&self) -> &Vec<definition::CVarDecl> {
+        &self.globals
+    }
+
+    pub fn add_vtable(&mut self, vtable: definition::CVarDecl) {
+        self.vtables.push(vtable);
+    }
+
+    pub fn get_vtables(&self) -> &Vec<definition::CVarDecl> {
+        &self.vtables
+    }
+
+    pub fn add_global_asm(&mut self, global_asm: asm::CGlobalAsm) {
+        self.global_asm.push(global_asm);
+    }
+
+    pub fn get_global_asm(&self) -> &Vec<asm::CGlobalAsm> {
+        &self.global_asm
+    }
 }
 
 pub struct OngoingCodegen {
@@ -204,6 +259,8 @@ impl OngoingCodegen {
                 false,
             ));
 
+        write::write_feature_macros(self.context.get_feature_macros(), &mut h_file);
+
         write::write_includes(
             self.context.get_c_includes(),
             self.context.get_h_includes(),
@@ -211,12 +268,18 @@ impl OngoingCodegen {
             &mut h_file,
         );
 
+        write::write_global_asm(self.context.get_global_asm(), &mut c_file);
+
         write::write_defines(self.context.get_defines(), &mut h_file);
 
         write::write_structs(self.context.get_structs(), &mut h_file);
 
+        write::write_globals(self.context.get_globals(), &mut h_file);
+
         write::write_prototypes(self.context.get_functions(), &mut h_file);
 
+        write::write_vtables(self.context.get_vtables(), &mut h_file);
+
         write::write_functions(self.context.get_mut_functions(), &mut c_file, false);
 
         write::write_functions(self.context.get_mut_header_functions(), &mut h_file, true);
@@ -262,12 +325,27 @@ fn transpile_cgu<'tcx, 'ccx>(
     alloc_to_c_map: &'ccx mut std::collections::HashMap<AllocId, Expression>,
 ) {
     for (item, _data) in cgu.items() {
-        if item.def_id().krate != 0u32.into() {
+        // drop glue is defined in core, but has to be generated for every crate that needs it
+        let is_drop_glue = matches!(
+            item,
+            MonoItem::Fn(Instance {
+                def: InstanceDef::DropGlue(_, Some(_)),
+                ..
+            })
+        );
+
+        if item.def_id().krate != 0u32.into() && !is_drop_glue {
             continue;
         }
 
         match item {
             MonoItem::Fn(inst) => {
+                // drop glue is instantiated in every codegen unit that uses it
+                let fn_name = function::format_fn_name(&tcx.symbol_name(*inst));
+                if is_drop_glue && ongoing_codegen.context.exists_fn_with_name(&fn_name) {
+                    continue;
+                }
+
                 with_no_trimmed_paths!({
                     function::handle_fn(
                         tcx,
@@ -279,11 +357,19 @@ fn transpile_cgu<'tcx, 'ccx>(
                     );
                 });
             }
-            MonoItem::Static(def) => {
-                panic!("Static items are not supported yet: {:?}", def);
+            MonoItem::Static(def_id) => {
+                with_no_trimmed_paths!({
+                    alloc::handle_static(
+                        tcx,
+                        ongoing_codegen,
+                        *def_id,
+                        rust_to_c_map,
+                        alloc_to_c_map,
+                    );
+                });
             }
             MonoItem::GlobalAsm(item_id) => {
-                panic!("Global asm items are not supported yet: {:?}", item_id);
+                asm::handle_global_asm(tcx, ongoing_codegen, *item_id);
             }
         }
     }
@@ -1,18 +1,26 @@
 use std::fmt::{self, Debug};
 
+use crate::asm::handle_inline_asm;
 use crate::crepr::{indent, Representable};
-use crate::expression::{Expression, UnaryOpType, VariableAccess};
+use crate::expression::{BinOpType, Expression, UnaryOpType, VariableAccess};
 use crate::fatptr::{FAT_PTR_DATA_FIELD, FAT_PTR_META_FIELD};
 use crate::function::{format_fn_name, CFunction, CodegenFunctionCx};
+use crate::int128;
+use crate::intrinsic;
+use crate::panic;
 use crate::stmt::{
-    handle_operand, handle_operand_with_access, handle_place, handle_stmt, Statement,
+    handle_operand, handle_operand_with_access, handle_place, handle_stmt, handle_unsized_ref,
+    Statement,
 };
 use crate::ty::CType;
+use crate::unwind;
+use crate::vtable;
 use rustc_middle::mir::BasicBlockData;
 use rustc_middle::mir::Operand;
 use rustc_middle::mir::TerminatorKind;
-use rustc_middle::ty::{InstanceDef, ParamEnv, TyKind};
+use rustc_middle::ty::{Instance, InstanceDef, ParamEnv, TyKind};
 use rustc_span::source_map::Spanned;
+use rustc_span::Span;
 use tracing::{debug, debug_span, warn};
 
 #[derive(Clone, PartialEq, Eq)]
@@ -109,10 +117,16 @@ fn handle_dynamic_dispatch<'tcx, 'ccx>(
         .expect("dynamic dispatch call must contain at least one arg")
         .clone();
 
-    let vtable_access = handle_operand_with_access(
-        fn_cx,
-        &vtable_arg.node,
-        vec![
+    let receiver_ty = fn_cx.monomorphize(vtable_arg.node.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
+
+    let access = match receiver_ty.builtin_deref(true) {
+        // the method is a field of the typed vtable of the trait object
+        Some(dyn_ty) if dyn_ty.is_trait() => {
+            let mut access = vtable::vtable_access(fn_cx, dyn_ty);
+            access.push(vtable::entry_field(fn_cx, dyn_ty, fn_offset));
+            access
+        }
+        _ => vec![
             VariableAccess::Field {
                 name: FAT_PTR_META_FIELD.to_string(),
             },
@@ -124,7 +138,8 @@ fn handle_dynamic_dispatch<'tcx, 'ccx>(
             },
             VariableAccess::Cast { ty: fn_type },
         ],
-    );
+    };
+    let vtable_access = handle_operand_with_access(fn_cx, &vtable_arg.node, access);
 
     let self_access = handle_operand_with_access(
         fn_cx,
@@ -153,7 +168,8 @@ fn handle_function_call<'tcx, 'ccx>(
     func: Operand<'tcx>,
     args: Vec<Spanned<Operand<'tcx>>>,
     destination: rustc_middle::mir::Place<'tcx>,
-) -> Statement {
+    fn_span: Span,
+) -> Vec<Statement> {
     let _span = debug_span!("handle_function_call").entered();
     debug!("Function call: {:?}, args {:?}", func, args);
 
@@ -174,6 +190,44 @@ fn handle_function_call<'tcx, 'ccx>(
                     )
                     .unwrap();
                     if let Some(instance) = instance {
+                        // drop_in_place of a type without drop glue does nothing
+                        if let InstanceDef::DropGlue(_, None) = instance.def {
+                            return vec![Statement::from_comment(format!(
+                                "No drop glue for {:?}",
+                                instance
+                            ))];
+                        }
+
+                        // explicit panics are routed to the panic handler
+                        if panic::is_panic_fn(fn_cx.tcx, instance.def_id()) {
+                            return vec![Statement::from_expression(panic::handle_explicit_panic(
+                                fn_cx, &args, fn_span,
+                            ))];
+                        }
+
+                        if let InstanceDef::Intrinsic(def_id) = instance.def {
+                            if fn_cx.tcx.item_name(def_id).as_str() == "catch_unwind" {
+                                let args = args
+                                    .iter()
+                                    .map(|arg| handle_operand(fn_cx, &arg.node))
+                                    .collect();
+                                return unwind::handle_catch_unwind(
+                                    &mut fn_cx.ongoing_codegen.context,
+                                    args,
+                                    destination,
+                                );
+                            }
+
+                            if let Some(stmt) = intrinsic::handle_intrinsic(
+                                fn_cx,
+                                instance,
+                                &args,
+                                destination.clone(),
+                            ) {
+                                return vec![stmt];
+                            }
+                        }
+
                         if let InstanceDef::Virtual(_def_id, fn_offset) = instance.def {
                             let fn_ty =
                                 instance.ty(fn_cx.tcx, rustc_middle::ty::ParamEnv::reveal_all());
@@ -215,15 +269,15 @@ fn handle_function_call<'tcx, 'ccx>(
                 }
             };
 
-            return Statement::from_expression(Expression::Assignment {
+            return vec![Statement::from_expression(Expression::Assignment {
                 lhs: Box::new(destination),
                 rhs: Box::new(fn_call),
-            });
+            })];
         }
 
         _ => {
             //TODO operand handling probably has to be changed to allow for functions as operands
-            
+
             let fn_call = Expression::FnCall {
                 function: Box::new(handle_operand(fn_cx, &func)),
                 args: args
@@ -232,10 +286,79 @@ fn handle_function_call<'tcx, 'ccx>(
                     .collect(),
             };
 
-            return Statement::from_expression(Expression::Assignment {
+            return vec![Statement::from_expression(Expression::Assignment {
                 lhs: Box::new(destination),
                 rhs: Box::new(fn_call),
+            })];
+        }
+    }
+}
+
+/// Calls the drop glue of the place's type with a pointer to the place
+fn handle_drop<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    place: &rustc_middle::mir::Place<'tcx>,
+) -> Vec<Statement> {
+    let ty = fn_cx.monomorphize(place.ty(&fn_cx.mir.local_decls, fn_cx.tcx).ty);
+    let drop_instance = Instance::resolve_drop_in_place(fn_cx.tcx, ty);
+
+    debug!("Drop {:?} with {:?}", place, drop_instance);
+
+    match drop_instance.def {
+        InstanceDef::DropGlue(_, None) => vec![],
+
+        _ if ty.is_trait() => {
+            // the drop function of trait objects lives in the vtable
+            let Some(Expression::Variable { local, access }) = handle_unsized_ref(fn_cx, place)
+            else {
+                panic!("Expected trait object behind a fat pointer: {:?}", place);
+            };
+
+            let mut drop_fn_access = access.clone();
+            drop_fn_access.extend(vtable::vtable_access(fn_cx, ty));
+            drop_fn_access.push(vtable::drop_in_place_field());
+            let drop_fn = Expression::Variable {
+                local,
+                access: drop_fn_access,
+            };
+
+            let mut data_access = access;
+            data_access.push(VariableAccess::Field {
+                name: FAT_PTR_DATA_FIELD.to_string(),
             });
+
+            // types without drop glue have no drop function
+            vec![Statement::from_expression(Expression::If {
+                cond: Box::new(drop_fn.clone()),
+                then: Box::new(Expression::FnCall {
+                    function: Box::new(drop_fn),
+                    args: vec![Expression::Variable {
+                        local,
+                        access: data_access,
+                    }],
+                }),
+            })]
+        }
+
+        _ => {
+            // unsized places are passed as the fat pointer they were dereferenced from
+            let arg = match handle_unsized_ref(fn_cx, place) {
+                Some(fat_ptr) => fat_ptr,
+                None => match handle_place(fn_cx, place) {
+                    Expression::Variable { local, mut access } => {
+                        access.push(VariableAccess::Reference);
+                        Expression::Variable { local, access }
+                    }
+                    _ => panic!("Expected place to be a variable"),
+                },
+            };
+
+            vec![Statement::from_expression(Expression::FnCall {
+                function: Box::new(Expression::Constant {
+                    value: format_fn_name(&fn_cx.tcx.symbol_name(drop_instance)),
+                }),
+                args: vec![arg],
+            })]
         }
     }
 }
@@ -253,21 +376,39 @@ pub fn handle_terminator<'tcx, 'ccx>(
             args,
             destination,
             target,
-            unwind: _,
+            unwind,
             call_source: _,
-            fn_span: _,
+            fn_span,
         } => {
-            let fn_call = handle_function_call(fn_cx, func, args, destination);
+            let fn_call = handle_function_call(fn_cx, func, args, destination, fn_span);
+
+            let mut stmts = unwind::with_landing_pad(&unwind, fn_call);
 
             if let Some(target) = target {
-                let stmt = Statement::from_expression(Expression::Goto {
+                stmts.push(Statement::from_expression(Expression::Goto {
                     target: BasicBlockIdentifier(target.as_usize()),
-                });
-
-                return vec![fn_call, stmt];
-            } else {
-                return vec![fn_call];
+                }));
             }
+
+            return stmts;
+        }
+
+        TerminatorKind::UnwindResume => {
+            return vec![Statement::from_expression(unwind::resume_unwind())];
+        }
+
+        TerminatorKind::UnwindTerminate(reason) => {
+            let stmt = Statement::new(
+                Expression::FnCall {
+                    function: Box::new(Expression::Constant {
+                        value: "abort".to_string(),
+                    }),
+                    args: vec![],
+                },
+                format!("Unwind terminate: {:?}", reason),
+            );
+
+            return vec![stmt];
         }
 
         TerminatorKind::Return => {
@@ -279,16 +420,63 @@ pub fn handle_terminator<'tcx, 'ccx>(
         }
 
         TerminatorKind::SwitchInt { discr, targets } => {
+            let discr_ty = fn_cx.monomorphize(discr.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
+
+            // C can't switch on 128-bit integers, the cases are compared one after the other
+            if int128::is_int128(&discr_ty) {
+                let value = handle_operand(fn_cx, &discr);
+                let c_ty = fn_cx.rust_to_c_type(&discr_ty);
+                let context = &mut fn_cx.ongoing_codegen.context;
+
+                let mut stmts = targets
+                    .iter()
+                    .map(|(val, target)| {
+                        let case = int128::literal(context, val, &c_ty);
+                        let cond = int128::handle_binary_op(
+                            context,
+                            BinOpType::Eq,
+                            value.clone(),
+                            case,
+                            &discr_ty,
+                            &discr_ty,
+                        );
+                        Statement::from_expression(Expression::If {
+                            cond: Box::new(cond),
+                            then: Box::new(Expression::Goto {
+                                target: BasicBlockIdentifier(target.as_usize()),
+                            }),
+                        })
+                    })
+                    .collect::<Vec<Statement>>();
+                stmts.push(Statement::from_expression(Expression::Goto {
+                    target: BasicBlockIdentifier(targets.otherwise().as_usize()),
+                }));
+
+                return stmts;
+            }
+
+            // values of signed discriminants (e.g. the tag of Ordering) are sign extended
+            let signed_bits = match discr_ty.kind() {
+                TyKind::Int(_) => Some(discr_ty.int_size_and_signed(fn_cx.tcx).0.bits()),
+                _ => None,
+            };
+
             let stmt = Statement::from_expression(Expression::SwitchJump {
                 value: Box::new(handle_operand(fn_cx, &discr)),
 
                 cases: targets
                     .iter()
                     .map(|(val, target)| {
+                        let value = match signed_bits {
+                            Some(bits) => {
+                                let shift = 128 - bits;
+                                format!("{}", ((val << shift) as i128) >> shift)
+                            }
+                            None => format!("{}", (&val)),
+                        };
+
                         (
-                            Box::new(Expression::Constant {
-                                value: format!("{}", (&val)),
-                            }), // TODO replace with proper constant representation
+                            Box::new(Expression::Constant { value }), // TODO replace with proper constant representation
                             BasicBlockIdentifier(target.as_usize()),
                         )
                     })
@@ -326,37 +514,38 @@ pub fn handle_terminator<'tcx, 'ccx>(
         TerminatorKind::Assert {
             cond,
             expected,
-            msg: _,
+            msg,
             target,
-            ..
+            unwind,
         } => {
-            /*  TODO we could ignore asserts, implement them with the assert define or allow the user to provide custom implementations to handle them.
-                I personally think the latter would be best and would allow us to side-step other similar issues.
-                We would have a default implementation that would use the assert macro, but the user could provide their own implementation.
-            */
-            let mut assert_operand = handle_operand(fn_cx, &cond);
-            if !expected {
-                assert_operand = Expression::UnaryOp {
+            let mut failed_operand = handle_operand(fn_cx, &cond);
+            if expected {
+                failed_operand = Expression::UnaryOp {
                     op: UnaryOpType::Not,
-                    val: Box::new(assert_operand),
+                    val: Box::new(failed_operand),
                 };
             }
 
-            let assert_stmt = Statement::from_expression(Expression::FnCall {
-                function: Box::new(Expression::Constant {
-                    value: "assert".to_string(),
-                }),
-                args: vec![assert_operand],
+            // the panic function aborts, or unwinds into the landing pad of the assert
+            let assert_stmt = Statement::from_expression(Expression::If {
+                cond: Box::new(failed_operand),
+                then: Box::new(panic::handle_assert_failure(
+                    fn_cx,
+                    &msg,
+                    terminator.source_info.span,
+                )),
             });
 
-            let stmt = Statement::new(
+            let mut stmts = unwind::with_landing_pad(&unwind, vec![assert_stmt]);
+
+            stmts.push(Statement::new(
                 Expression::Goto {
                     target: BasicBlockIdentifier(target.as_usize()),
                 },
                 format!("Assert: {:?}", cond),
-            );
+            ));
 
-            return vec![assert_stmt, stmt];
+            return stmts;
         }
 
         TerminatorKind::Goto { target } => {
@@ -369,6 +558,27 @@ pub fn handle_terminator<'tcx, 'ccx>(
             return vec![stmt];
         }
 
+        TerminatorKind::Drop {
+            place,
+            target,
+            unwind,
+            replace: _,
+        } => {
+            let drop_stmts = handle_drop(fn_cx, &place);
+
+            let mut stmts = if drop_stmts.is_empty() {
+                drop_stmts
+            } else {
+                unwind::with_landing_pad(&unwind, drop_stmts)
+            };
+
+            stmts.push(Statement::from_expression(Expression::Goto {
+                target: BasicBlockIdentifier(target.as_usize()),
+            }));
+
+            return stmts;
+        }
+
         TerminatorKind::InlineAsm {
             template,
             operands,
@@ -382,32 +592,7 @@ pub fn handle_terminator<'tcx, 'ccx>(
                 template, operands, options, line_spans, targets, unwind
             );
 
-            template
-                .iter()
-                .flat_map(|piece| match piece {
-                    rustc_ast::ast::InlineAsmTemplatePiece::String(s) => {
-                        if s.trim().is_empty() {
-                            return vec![];
-                        }
-                        vec![Statement::from_expression(Expression::InlineAsm {
-                            asm: s.clone(),
-                        })]
-                    }
-
-                    rustc_ast::ast::InlineAsmTemplatePiece::Placeholder {
-                        operand_idx: _,
-                        modifier: _,
-                        span: _,
-                    } => {
-                        // let operand = operands[*operand_idx];
-
-                        // let expr = handle_operand(fn_cx, &operand);
-
-                        // vec![Statement::from_expression(Expression::InlineAsm { asm: () })]
-                        panic!("Unimplemented InlineAsm Placeholder");
-                    }
-                })
-                .collect()
+            handle_inline_asm(fn_cx, template, operands, *options, targets)
         }
 
         _ => {
@@ -1,12 +1,13 @@
 use crate::{
+    asm::CInlineAsm,
     bb::BasicBlockIdentifier,
     crepr::{indent, Representable, RepresentationContext},
     fatptr::{FAT_PTR_DATA_FIELD, FAT_PTR_NAME},
-    ty::CType,
+    ty::{CType, CUIntTy},
 };
 use std::{
     fmt,
-    ops::{Add, BitAnd, BitOr, Div, Mul, Sub},
+    ops::{Add, BitAnd, BitOr, Div, Mul, Shl, Shr, Sub},
 };
 
 #[allow(dead_code)]
@@ -57,9 +58,13 @@ impl From<&rustc_middle::mir::BinOp> for BinOpType {
             rustc_middle::mir::BinOp::MulUnchecked => BinOpType::Mul,
             rustc_middle::mir::BinOp::ShlUnchecked => BinOpType::Shl,
             rustc_middle::mir::BinOp::ShrUnchecked => BinOpType::Shr,
-            rustc_middle::mir::BinOp::Cmp => BinOpType::Eq,
+            rustc_middle::mir::BinOp::Cmp => {
+                panic!("BinOp::Cmp has no single C operator, use handle_cmp instead")
+            }
 
-            rustc_middle::mir::BinOp::Offset => BinOpType::Add, // TODO this is a guess
+            rustc_middle::mir::BinOp::Offset => {
+                panic!("BinOp::Offset is scaled by the pointee size, use handle_offset instead")
+            }
             rustc_middle::mir::BinOp::AddWithOverflow => BinOpType::CheckedAdd,
             rustc_middle::mir::BinOp::SubWithOverflow => BinOpType::CheckedSub,
             rustc_middle::mir::BinOp::MulWithOverflow => BinOpType::CheckedMul,
@@ -124,6 +129,8 @@ pub enum UnaryOpType {
     Neg,
     Not,
     BitNot,
+    // dereference of an arbitrary pointer expression, variables use VariableAccess::Dereference
+    Deref,
 }
 
 impl Representable for UnaryOpType {
@@ -132,6 +139,7 @@ impl Representable for UnaryOpType {
             UnaryOpType::Neg => write!(f, "-"),
             UnaryOpType::Not => write!(f, "!"),
             UnaryOpType::BitNot => write!(f, "~"),
+            UnaryOpType::Deref => write!(f, "*"),
         }
     }
 }
@@ -213,12 +221,20 @@ pub enum Expression {
         args: Vec<Expression>,
     },
     InlineAsm {
-        asm: String,
+        asm: Box<CInlineAsm>,
     },
     Cast {
         ty: CType,
         value: Box<Expression>,
     },
+    If {
+        cond: Box<Expression>,
+        then: Box<Expression>,
+    },
+    // braced initializer of a nested aggregate, e.g. the lanes of a vector
+    InitializerList {
+        fields: Vec<Expression>,
+    },
 }
 impl Expression {
     /// Returns Expression::Assignment
@@ -242,6 +258,20 @@ impl Expression {
             rhs,
         })
     }
+    pub fn ge(&self, rhs: Box<Expression>) -> Box<Expression> {
+        Box::new(Expression::BinaryOp {
+            op: BinOpType::Ge,
+            lhs: Box::new(self.clone()),
+            rhs,
+        })
+    }
+    pub fn le(&self, rhs: Box<Expression>) -> Box<Expression> {
+        Box::new(Expression::BinaryOp {
+            op: BinOpType::Le,
+            lhs: Box::new(self.clone()),
+            rhs,
+        })
+    }
     pub fn neq(&self, rhs: Box<Expression>) -> Box<Expression> {
         Box::new(Expression::BinaryOp {
             op: BinOpType::Ne,
@@ -291,6 +321,70 @@ impl Expression {
         }
     }
 
+    /// `lhs op rhs`, wrapping around on overflow like Rust does.
+    /// C computes in at least `int`, where signed overflow is undefined, so integers are converted
+    /// to an unsigned type at least as wide as `unsigned int` and the result is truncated back to `ty`
+    pub fn wrapping(op: BinOpType, lhs: Expression, rhs: Expression, ty: &CType) -> Expression {
+        let Some(unsigned_ty) = wrapping_ty(ty) else {
+            return Expression::BinaryOp {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        };
+
+        Expression::Cast {
+            ty: ty.clone(),
+            value: Box::new(Expression::BinaryOp {
+                op,
+                lhs: Box::new(Expression::Cast {
+                    ty: unsigned_ty.clone(),
+                    value: Box::new(lhs),
+                }),
+                rhs: Box::new(Expression::Cast {
+                    ty: unsigned_ty,
+                    value: Box::new(rhs),
+                }),
+            }),
+        }
+    }
+
+    /// `-val`, wrapping around for the minimum value of `ty`
+    pub fn wrapping_neg(val: Expression, ty: &CType) -> Expression {
+        let Some(unsigned_ty) = wrapping_ty(ty) else {
+            return Expression::UnaryOp {
+                op: UnaryOpType::Neg,
+                val: Box::new(val),
+            };
+        };
+
+        Expression::Cast {
+            ty: ty.clone(),
+            value: Box::new(Expression::UnaryOp {
+                op: UnaryOpType::Neg,
+                val: Box::new(Expression::Cast {
+                    ty: unsigned_ty,
+                    value: Box::new(val),
+                }),
+            }),
+        }
+    }
+
+    /// Represents the expression, parenthesized if it is a binary operation
+    fn repr_operand(
+        &self,
+        f: &mut (dyn fmt::Write),
+        context: &mut RepresentationContext,
+    ) -> fmt::Result {
+        if let Expression::BinaryOp { .. } = self {
+            write!(f, "(")?;
+            self.repr(f, context)?;
+            write!(f, ")")
+        } else {
+            self.repr(f, context)
+        }
+    }
+
     pub fn fatptr(data: Expression, meta: Expression) -> Expression {
         Expression::Struct {
             name: Box::new(Expression::Constant {
@@ -366,6 +460,28 @@ impl BitAnd for Box<Expression> {
         })
     }
 }
+impl Shl for Box<Expression> {
+    type Output = Self;
+
+    fn shl(self, rhs: Self) -> Self {
+        Box::new(Expression::BinaryOp {
+            op: BinOpType::Shl,
+            lhs: self,
+            rhs,
+        })
+    }
+}
+impl Shr for Box<Expression> {
+    type Output = Self;
+
+    fn shr(self, rhs: Self) -> Self {
+        Box::new(Expression::BinaryOp {
+            op: BinOpType::Shr,
+            lhs: self,
+            rhs,
+        })
+    }
+}
 
 impl Representable for Expression {
     fn repr(&self, f: &mut (dyn fmt::Write), context: &mut RepresentationContext) -> fmt::Result {
@@ -436,18 +552,19 @@ impl Representable for Expression {
             }
             Expression::BinaryOp { op, lhs, rhs } => {
                 // {} {} {} (eg. {1} {+} {5})
-                lhs.repr(f, context)?;
+                // nested operations are parenthesized, since C precedence doesn't match the tree
+                lhs.repr_operand(f, context)?;
                 write!(f, " ")?;
                 op.repr(f, context)?;
                 write!(f, " ")?;
-                rhs.repr(f, context)?;
+                rhs.repr_operand(f, context)?;
                 Ok(())
             }
 
             Expression::UnaryOp { op, val } => {
                 // {}{} (eg. {-}{5})
                 op.repr(f, context)?;
-                val.repr(f, context)?;
+                val.repr_operand(f, context)?;
                 Ok(())
             }
 
@@ -464,6 +581,17 @@ impl Representable for Expression {
                 }
                 write!(f, " }}")
             }
+            Expression::InitializerList { fields } => {
+                // { {}, {} } (eg. { 1, 2 })
+                write!(f, "{{ ")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    field.repr(f, context)?;
+                }
+                write!(f, " }}")
+            }
             Expression::Repeat { lhs, value, count } => {
                 write!(f, "for(uint64_t i = 0; i < {}; i++){{", count)?;
                 lhs.repr(f, context)?;
@@ -567,15 +695,32 @@ impl Representable for Expression {
                 Ok(())
             }
 
-            Expression::InlineAsm { asm } => {
-                write!(f, "asm(\"{}\")", asm)
-            }
+            Expression::InlineAsm { asm } => asm.repr(f, context),
             Expression::Cast { ty, value } => {
                 write!(f, "(")?;
                 ty.repr(f, context)?;
                 write!(f, ")")?;
-                value.repr(f, context)
+
+                // the cast would otherwise only apply to the left operand
+                value.repr_operand(f, context)
+            }
+            Expression::If { cond, then } => {
+                write!(f, "if (")?;
+                cond.repr(f, context)?;
+                write!(f, ") ")?;
+                then.repr(f, context)
             }
         }
     }
 }
+
+/// Unsigned type integer arithmetic on `ty` is done in, None for non integer types
+fn wrapping_ty(ty: &CType) -> Option<CType> {
+    let bit_width = match ty {
+        CType::Int(int_ty) => int_ty.bit_width(),
+        CType::UInt(uint_ty) => uint_ty.bit_width(),
+        _ => return None,
+    };
+
+    Some(CType::UInt(CUIntTy::from(bit_width.max(32))))
+}
@@ -0,0 +1,114 @@
+use crate::{base::Context, header::CDefine};
+
+pub const F16_NAME: &str = "_Float16";
+pub const F128_NAME: &str = "codegenc_f128";
+
+// long double is only usable where it is IEEE binary128
+const LONG_DOUBLE_CONDITION: &str = "LDBL_MANT_DIG == 113";
+// GCC has _Float128, Clang only the older __float128 extension
+const FLOAT128_CONDITION: &str = "defined(__FLT128_MAX__)";
+/// Where f128 is __float128, its math functions are declared in quadmath.h and need -lquadmath
+pub const QUADMATH_CONDITION: &str =
+    "LDBL_MANT_DIG != 113 && !defined(__FLT128_MAX__) && __has_include(<quadmath.h>)";
+
+/// (name, long double, _Float128, __float128) of the type, literal and math function macros
+const F128_DEFINES: &[(&str, &str, &str, &str)] = &[
+    ("codegenc_f128", "long double", "_Float128", "__float128"),
+    ("codegenc_f128_lit(x)", "x##L", "x##f128", "x##q"),
+    // __float128 functions come from libquadmath
+    ("codegenc_f128_fn(name)", "name##l", "name##f128", "name##q"),
+];
+
+// #if LDBL_MANT_DIG == 113
+// #define codegenc_f128 long double
+// #else
+// #define codegenc_f128 codegenc_f128_ext
+// #endif
+// #if defined(__FLT128_MAX__)
+// #define codegenc_f128_ext _Float128
+// #else
+// #define codegenc_f128_ext __float128
+// #endif
+/// Declares the f128 type, along with the macros for its literals and math functions
+pub fn add_f128_types(context: &mut Context) {
+    if context.has_define_with_name(&F128_NAME.to_string()) {
+        return;
+    }
+
+    for (name, long_double, float128, gnu_float128) in F128_DEFINES {
+        // e.g. codegenc_f128_lit(x) -> codegenc_f128_ext_lit(x)
+        let ext_name = name.replacen("codegenc_f128", "codegenc_f128_ext", 1);
+
+        context.get_mut_defines().push(CDefine::new_conditional(
+            name.to_string(),
+            LONG_DOUBLE_CONDITION.to_string(),
+            long_double.to_string(),
+            ext_name.clone(),
+        ));
+        context.get_mut_defines().push(CDefine::new_conditional(
+            ext_name,
+            FLOAT128_CONDITION.to_string(),
+            float128.to_string(),
+            gnu_float128.to_string(),
+        ));
+    }
+}
+
+/// The C literal of a float of `bit_width` bits, given its raw bits.
+/// Literals are written as hex floats, which represent the value exactly
+pub fn literal(bits: u128, bit_width: u64) -> String {
+    let (exp_bits, mant_bits) = match bit_width {
+        16 => (5, 10),
+        32 => (8, 23),
+        64 => (11, 52),
+        128 => (15, 112),
+        _ => panic!("Unsupported float size!"),
+    };
+
+    let negative = (bits >> (exp_bits + mant_bits)) & 1 == 1;
+    let exp = ((bits >> mant_bits) & ((1 << exp_bits) - 1)) as i64;
+    let mant = bits & ((1 << mant_bits) - 1);
+    let bias = (1i64 << (exp_bits - 1)) - 1;
+    let sign = if negative { "-" } else { "" };
+
+    // infinities and NaNs have no literal
+    if exp == (1 << exp_bits) - 1 {
+        let value = if mant != 0 {
+            "NAN".to_string()
+        } else {
+            format!("{sign}INFINITY")
+        };
+        return match bit_width {
+            16 => format!("(({F16_NAME})({value}))"),
+            32 => value,
+            64 => format!("((double)({value}))"),
+            _ => format!("(({F128_NAME})({value}))"),
+        };
+    }
+
+    // subnormals have no implicit leading one
+    let (lead, exp) = match (exp, mant) {
+        (0, 0) => (0, 0),
+        (0, _) => (0, 1 - bias),
+        _ => (1, exp - bias),
+    };
+
+    // the mantissa is padded to whole hex digits
+    let pad = (4 - mant_bits % 4) % 4;
+    let digits = ((mant_bits + pad) / 4) as usize;
+    let fraction = format!("{:0digits$x}", mant << pad);
+    let fraction = fraction.trim_end_matches('0');
+    let hex = if fraction.is_empty() {
+        format!("0x{lead}p{exp:+}")
+    } else {
+        format!("0x{lead}.{fraction}p{exp:+}")
+    };
+
+    match bit_width {
+        // every f16 is exactly representable as a float
+        16 => format!("{sign}(({F16_NAME}){hex}f)"),
+        32 => format!("{sign}{hex}f"),
+        64 => format!("{sign}{hex}"),
+        _ => format!("{sign}codegenc_f128_lit({hex})"),
+    }
+}
@@ -3,15 +3,18 @@ use crate::crepr::{indent, Representable, RepresentationContext};
 use crate::definition::CVarDef;
 use crate::expression::Expression;
 use crate::ty::CType;
+use crate::unwind;
+use crate::vtable;
 use crate::{base::OngoingCodegen, definition::CVarDecl};
 use rustc_const_eval::interpret::ConstAllocation;
+use rustc_hir::def_id::DefId;
 use rustc_middle::mir::interpret::{AllocId, GlobalAlloc};
 use rustc_middle::ty::{self, Instance, SymbolName, TyCtxt, TypeFoldable};
 // use stable_mir::mir::alloc::{AllocId, GlobalAlloc};
 use std::collections::HashSet;
 use std::fmt::{self, Debug};
 
-use tracing::{debug, trace, warn};
+use tracing::{debug, trace};
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct CFunction {
@@ -21,6 +24,10 @@ pub struct CFunction {
     basic_blocks: Vec<BasicBlock>,
     is_main: bool,
     return_ty: CType,
+    // weak definitions can be overridden by a definition with the same name at link time
+    is_weak: bool,
+    // extern functions are defined by the user, only their prototype is emitted
+    is_extern: bool,
 }
 
 pub struct CodegenFunctionCx<'tcx, 'ccx> {
@@ -35,7 +42,7 @@ pub struct CodegenFunctionCx<'tcx, 'ccx> {
     pub(crate) alloc_to_c: &'ccx mut std::collections::HashMap<AllocId, Expression>,
 }
 
-impl<'tcx> CodegenFunctionCx<'tcx, '_> {
+impl<'tcx, 'ccx> CodegenFunctionCx<'tcx, 'ccx> {
     pub fn monomorphize<T>(&self, value: T) -> T
     where
         T: Copy + TypeFoldable<TyCtxt<'tcx>>,
@@ -47,6 +54,27 @@ impl<'tcx> CodegenFunctionCx<'tcx, '_> {
         )
     }
 
+    /// Statics have no body to lower, the MIR of their initializer stands in for it,
+    /// so the allocations they point to share the type and allocation caches of functions
+    pub fn new_for_static(
+        tcx: TyCtxt<'tcx>,
+        ongoing_codegen: &'ccx mut OngoingCodegen,
+        def_id: DefId,
+        ty_to_c: &'ccx mut std::collections::HashMap<ty::Ty<'tcx>, CType>,
+        alloc_to_c: &'ccx mut std::collections::HashMap<AllocId, Expression>,
+        crate_num: usize,
+    ) -> Self {
+        Self {
+            tcx,
+            mir: tcx.mir_for_ctfe(def_id),
+            ongoing_codegen,
+            instance: Instance::mono(tcx, def_id),
+            crate_num,
+            ty_to_c,
+            alloc_to_c,
+        }
+    }
+
     pub fn ty_for_local(&self, local: rustc_middle::mir::Local) -> ty::Ty<'tcx> {
         self.monomorphize(self.mir.local_decls[local].ty)
     }
@@ -56,47 +84,15 @@ impl<'tcx> CodegenFunctionCx<'tcx, '_> {
         self.ty_to_c.get(ty).cloned()
     }
 
-    fn handle_cosnt_alloc(&mut self, alloc: ConstAllocation, alloc_id: AllocId) -> Expression {
-        let inner_alloc = alloc.inner();
-
-        let alloc_bytes: Vec<u8> = inner_alloc
-            .inspect_with_uninit_and_ptr_outside_interpreter(0..inner_alloc.len())
-            .into();
-
-        let ptrs = inner_alloc.provenance().ptrs();
-
-        let mut ptr_declrs: Vec<(usize, Expression)> = Vec::new();
-
-        for (offset, prov) in ptrs.iter() {
-            let offset = u32::try_from(offset.bytes_usize()).unwrap();
-            // Check if this allocation is a function
-            let reloc_target_alloc = self.tcx.global_alloc(prov.alloc_id());
-
-            if let GlobalAlloc::Function(finstance) = reloc_target_alloc {
-                let fn_name = format_fn_name(&self.tcx.symbol_name(finstance));
-
-                ptr_declrs.push((offset as usize, Expression::Constant { value: fn_name }));
-            } else {
-                warn!("Not a function alloc: {:?}", reloc_target_alloc);
-
-                ptr_declrs.push((
-                    offset as usize,
-                    Expression::Constant {
-                        value: format!("ptr_{}", offset),
-                    },
-                ));
-            }
-        }
-
-        let alloc_name = format!("ALLOC_{}_CRATE_{}", alloc_id.0, self.crate_num);
-
-        let static_alloc =
-            crate::alloc::StaticAllocation::new(alloc_name.clone(), alloc_bytes, ptr_declrs);
-
-        self.ongoing_codegen.context.add_static(static_alloc);
+    fn handle_cosnt_alloc(
+        &mut self,
+        alloc: ConstAllocation<'tcx>,
+        alloc_id: AllocId,
+    ) -> Expression {
+        let alloc_name = crate::alloc::emit_memory_alloc(self, alloc_id, alloc);
 
         Expression::Constant {
-            value: format!("&{}", alloc_name.clone()),
+            value: format!("&{}", alloc_name),
         }
     }
 
@@ -110,8 +106,17 @@ impl<'tcx> CodegenFunctionCx<'tcx, '_> {
         let c_alloc = match global_alloc {
             GlobalAlloc::Memory(const_alloc) => self.handle_cosnt_alloc(const_alloc, alloc),
 
-            _ => {
-                panic!("Global alloc not handled: {:?}", global_alloc);
+            GlobalAlloc::Static(def_id) => Expression::Constant {
+                value: format!("&{}", crate::alloc::declare_static(self, def_id)),
+            },
+
+            GlobalAlloc::Function(instance) => Expression::Constant {
+                value: format_fn_name(&self.tcx.symbol_name(instance)),
+            },
+
+            GlobalAlloc::VTable(ty, trait_ref) => {
+                let dyn_ty = vtable::dyn_type(self.tcx, trait_ref);
+                vtable::vtable_ptr(self, ty, dyn_ty)
             }
         };
 
@@ -125,6 +130,12 @@ impl Representable for CFunction {
     fn repr(&self, f: &mut (dyn fmt::Write), context: &mut RepresentationContext) -> fmt::Result {
         let mut new_context = context.clone();
         new_context.cur_fn = Some(&self);
+        if self.is_extern {
+            return write!(f, "extern {}", self.as_prototype());
+        }
+        if self.is_weak {
+            write!(f, "__attribute__((weak)) ")?;
+        }
         self.return_ty.repr(f, &mut new_context)?;
         write!(f, " {}(", self.name)?;
         for (i, arg) in self.signature.iter().enumerate() {
@@ -165,9 +176,19 @@ impl CFunction {
             basic_blocks: Vec::new(),
             is_main: false,
             return_ty: return_ty,
+            is_weak: false,
+            is_extern: false,
         }
     }
 
+    pub fn set_weak(&mut self) {
+        self.is_weak = true;
+    }
+
+    pub fn set_extern(&mut self) {
+        self.is_extern = true;
+    }
+
     pub fn is_main(&self) -> bool {
         self.is_main
     }
@@ -321,6 +342,14 @@ pub fn handle_fn<'tcx, 'ccx>(
     // Handle local variables
     handle_decls(&mut fn_cx, &mut c_fn);
 
+    if unwind::needs_unwind_frame(&mono_mir) {
+        unwind::add_unwind_frame(
+            &mut c_fn,
+            &fn_cx.ongoing_codegen.context,
+            mono_mir.local_decls.len(),
+        );
+    }
+
     trace!("{:?}", c_fn);
 
     // Handle basic blocks
@@ -6,9 +6,10 @@ use crate::{
     definition::{CVarDecl, CVarDef},
     expression::{BinOpType, Expression},
     function::{CFunction, CodegenFunctionCx},
+    int128,
     stmt::Statement,
     structure::CStructDef,
-    ty::CType,
+    ty::{CIntTy, CType, CUIntTy},
 };
 use rustc_middle::ty::Ty;
 use tracing::{debug, debug_span};
@@ -17,6 +18,8 @@ use tracing::{debug, debug_span};
 pub struct CDefine {
     name: String,
     value: String,
+    // (condition, fallback value) for defines that differ between compilers
+    conditional: Option<(String, String)>,
 }
 impl Representable for CDefine {
     fn repr(
@@ -24,7 +27,16 @@ impl Representable for CDefine {
         f: &mut (dyn fmt::Write),
         _context: &mut crate::crepr::RepresentationContext,
     ) -> fmt::Result {
-        write!(f, "#define {} {}", self.name, self.value)
+        match &self.conditional {
+            None => write!(f, "#define {} {}", self.name, self.value),
+            Some((condition, fallback)) => {
+                write!(f, "#if {}\n", condition)?;
+                write!(f, "#define {} {}\n", self.name, self.value)?;
+                write!(f, "#else\n")?;
+                write!(f, "#define {} {}\n", self.name, fallback)?;
+                write!(f, "#endif")
+            }
+        }
     }
 }
 
@@ -35,7 +47,24 @@ impl Debug for CDefine {
 }
 impl CDefine {
     pub fn new(name: String, value: String) -> Self {
-        Self { name, value }
+        Self {
+            name,
+            value,
+            conditional: None,
+        }
+    }
+    /// Defines `name` as `value` if the preprocessor `condition` holds, and as `fallback` otherwise
+    pub fn new_conditional(
+        name: String,
+        condition: String,
+        value: String,
+        fallback: String,
+    ) -> Self {
+        Self {
+            name,
+            value,
+            conditional: Some((condition, fallback)),
+        }
     }
     pub fn get_name(&self) -> String {
         self.name.clone()
@@ -77,23 +106,23 @@ pub fn handle_checked_op<'tcx, 'ccx>(
         let checked_op = match op {
             BinOpType::CheckedAdd => {
                 if ty.is_signed() {
-                    signed_add(fn_cx, &fn_name, &c_struct, return_ty)
+                    signed_add(fn_cx, &fn_name, &c_struct, return_ty, ty)
                 } else {
-                    unsigned_add(fn_cx, &fn_name, &c_struct, return_ty)
+                    unsigned_add(fn_cx, &fn_name, &c_struct, return_ty, ty)
                 }
             }
             BinOpType::CheckedSub => {
                 if ty.is_signed() {
-                    signed_sub(fn_cx, &fn_name, &c_struct, return_ty)
+                    signed_sub(fn_cx, &fn_name, &c_struct, return_ty, ty)
                 } else {
-                    unsigned_sub(fn_cx, &fn_name, &c_struct, return_ty)
+                    unsigned_sub(fn_cx, &fn_name, &c_struct, return_ty, ty)
                 }
             }
             BinOpType::CheckedMul => {
                 if ty.is_signed() {
-                    signed_mul(fn_cx, &fn_name, &c_struct, return_ty)
+                    signed_mul(fn_cx, &fn_name, &c_struct, return_ty, ty)
                 } else {
-                    unsigned_mul(fn_cx, &fn_name, &c_struct, return_ty)
+                    unsigned_mul(fn_cx, &fn_name, &c_struct, return_ty, ty)
                 }
             }
             _ => {
@@ -113,91 +142,250 @@ pub fn handle_checked_op<'tcx, 'ccx>(
     }
 }
 
+/// Divides or takes the remainder of integers without the undefined behavior of C:
+/// MIR asserts against division by zero and overflow, but unchecked intrinsics skip the asserts,
+/// so a zero divisor gives 0 and `MIN / -1` wraps around to `MIN`
+pub fn handle_div_rem<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: BinOpType,
+    lhs: Expression,
+    rhs: Expression,
+    ty: &Ty<'tcx>,
+) -> Expression {
+    let fn_name = format!("{op}_{ty:?}");
+
+    if !fn_cx
+        .ongoing_codegen
+        .context
+        .exists_header_fn_with_name(fn_name.as_str())
+    {
+        debug!("Function for {fn_name} not found, creating one!");
+        let c_ty = fn_cx.rust_to_c_type(ty);
+
+        let mut c_fn = CFunction::new(fn_name.clone(), c_ty.clone());
+        c_fn.add_signature_var(CVarDef::new(0, "lhs".to_string(), c_ty.clone()));
+        c_fn.add_signature_var(CVarDef::new(1, "rhs".to_string(), c_ty.clone()));
+
+        let return_if = |divisor: i128, value: Expression| {
+            Statement::from_expression(Expression::If {
+                cond: Expression::vari(1).equ(Box::new(Expression::const_int(divisor))),
+                then: Box::new(Expression::Return {
+                    value: Box::new(value),
+                }),
+            })
+        };
+
+        let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+        bb.push(return_if(0, Expression::const_int(0)));
+        if ty.is_signed() {
+            // the only overflowing case, MIN / -1, is the same as negating
+            bb.push(return_if(
+                -1,
+                match op {
+                    BinOpType::Div => Expression::wrapping_neg(Expression::unbvari(0), &c_ty),
+                    _ => Expression::const_int(0),
+                },
+            ));
+        }
+        bb.push(Statement::from_expression(Expression::Return {
+            value: Box::new(Expression::BinaryOp {
+                op,
+                lhs: Expression::vari(0),
+                rhs: Expression::vari(1),
+            }),
+        }));
+        c_fn.push_bb(bb);
+
+        fn_cx
+            .ongoing_codegen
+            .context
+            .get_mut_header_functions()
+            .push(c_fn);
+    }
+
+    Expression::FnCall {
+        function: Box::new(Expression::Constant { value: fn_name }),
+        args: vec![lhs, rhs],
+    }
+}
+
+/// Casts a float to an integer with Rust's semantics: out of range values saturate and NaN becomes 0,
+/// where a C cast would be undefined
+pub fn handle_float_to_int<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    value: Expression,
+    float_ty: &Ty<'tcx>,
+    int_ty: &Ty<'tcx>,
+) -> Expression {
+    let fn_name = format!("float_to_int_{float_ty:?}_{int_ty:?}");
+
+    if !fn_cx
+        .ongoing_codegen
+        .context
+        .exists_header_fn_with_name(fn_name.as_str())
+    {
+        debug!("Function for {fn_name} not found, creating one!");
+        let float_type = fn_cx.rust_to_c_type(float_ty);
+        let int_type = fn_cx.rust_to_c_type(int_ty);
+        let (max_int, min_int) = extremum_val_of_type(fn_cx, &int_type);
+
+        // the float values saturating to the limits, 128-bit limits can't be cast to a float without a native type,
+        // but the bounds are powers of two exact as hex floats
+        let (max_float, min_float) = match int_type {
+            CType::Int(CIntTy::Int128) => (float_const("0x1p127"), float_const("-0x1p127")),
+            CType::UInt(CUIntTy::UInt128) => (float_const("0x1p128"), float_const("0")),
+            _ => (
+                Box::new(Expression::Cast {
+                    ty: float_type.clone(),
+                    value: Box::new(max_int.clone()),
+                }),
+                Box::new(Expression::Cast {
+                    ty: float_type.clone(),
+                    value: Box::new(min_int.clone()),
+                }),
+            ),
+        };
+
+        let mut c_fn = CFunction::new(fn_name.clone(), int_type.clone());
+        c_fn.add_signature_var(CVarDef::new(0, "value".to_string(), float_type.clone()));
+
+        let saturate = |cond: Box<Expression>, limit: Expression| {
+            Statement::from_expression(Expression::If {
+                cond,
+                then: Box::new(Expression::Return {
+                    value: Box::new(limit),
+                }),
+            })
+        };
+
+        let (zero, converted) = if int128::is_int128(int_ty) {
+            let context = &mut fn_cx.ongoing_codegen.context;
+            (
+                int128::literal(context, 0, &int_type),
+                int128::from_float(context, Expression::unbvari(0), &float_type, &int_type),
+            )
+        } else {
+            (
+                Expression::const_int(0),
+                Expression::Cast {
+                    ty: int_type,
+                    value: Expression::vari(0),
+                },
+            )
+        };
+
+        let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+        // NaN is the only value not equal to itself
+        bb.push(saturate(Expression::vari(0).neq(Expression::vari(0)), zero));
+        // the limits might round away from zero when converted, in which case they are not reachable by the cast anyway
+        bb.push(saturate(Expression::vari(0).le(min_float), min_int));
+        bb.push(saturate(Expression::vari(0).ge(max_float), max_int));
+        bb.push(Statement::from_expression(Expression::Return {
+            value: Box::new(converted),
+        }));
+        c_fn.push_bb(bb);
+
+        fn_cx
+            .ongoing_codegen
+            .context
+            .get_mut_header_functions()
+            .push(c_fn);
+    }
+
+    Expression::FnCall {
+        function: Box::new(Expression::Constant { value: fn_name }),
+        args: vec![value],
+    }
+}
+
+/// The (max, min) values of an integer type
 fn extremum_val_of_type<'tcx, 'ccx>(
     fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     ty: &CType,
-) -> (String, String) {
+) -> (Expression, Expression) {
+    let constant = |value: String| Expression::Constant { value };
     match ty {
+        CType::Int(CIntTy::Int128) | CType::UInt(CUIntTy::UInt128) => {
+            let context = &mut fn_cx.ongoing_codegen.context;
+            (int128::max(context, ty), int128::min(context, ty))
+        }
         CType::Int(c_int_ty) => {
             let bit_width = c_int_ty.bit_width();
-            let max_uint = format!("UINT{bit_width}_MAX");
-            let max_int = format!("INT{bit_width}_MAX");
-            let min_int = format!("INT{bit_width}_MIN");
-            if bit_width == 128 {
-                if !fn_cx
-                    .ongoing_codegen
-                    .context
-                    .has_define_with_name(&max_uint)
-                {
-                    //#define UINT128_MAX (__uint128_t)(-1)
-                    fn_cx
-                        .ongoing_codegen
-                        .context
-                        .get_mut_defines()
-                        .push(CDefine::new(
-                            max_uint.clone(),
-                            "(__uint128_t)(-1)".to_string(),
-                        ));
-                }
-                if !fn_cx.ongoing_codegen.context.has_define_with_name(&max_int) {
-                    //#define INT128_MAX (__int128_t)(UINT128_MAX >> 1)
-                    fn_cx
-                        .ongoing_codegen
-                        .context
-                        .get_mut_defines()
-                        .push(CDefine::new(
-                            max_int.clone(),
-                            format!("(__int128_t)({max_uint} >> 1)"),
-                        ));
-                    //#define INT128_MIN (__int128_t)(-INT128_MAX - 1)
-                    fn_cx
-                        .ongoing_codegen
-                        .context
-                        .get_mut_defines()
-                        .push(CDefine::new(
-                            min_int.clone(),
-                            "(__int128_t)(-INT128_MAX - 1)".to_string(),
-                        ));
-                }
-            }
-            (max_int, min_int)
-        }
-        CType::UInt(c_uint_ty) => {
-            let bit_width = c_uint_ty.bit_width();
-            let max_uint = format!("UINT{bit_width}_MAX");
-            if bit_width == 128 {
-                if !fn_cx
-                    .ongoing_codegen
-                    .context
-                    .has_define_with_name(&max_uint)
-                {
-                    //#define UINT128_MAX (uint128_t)(-1)
-                    fn_cx
-                        .ongoing_codegen
-                        .context
-                        .get_mut_defines()
-                        .push(CDefine::new(
-                            max_uint.clone(),
-                            "(uint128_t)(-1)".to_string(),
-                        ));
-                }
-            }
-            (max_uint, "0".to_string())
+            (
+                constant(format!("INT{bit_width}_MAX")),
+                constant(format!("INT{bit_width}_MIN")),
+            )
         }
+        CType::UInt(c_uint_ty) => (
+            constant(format!("UINT{}_MAX", c_uint_ty.bit_width())),
+            constant("0".to_string()),
+        ),
         _ => panic!("Expected integer type, found {:?}", ty),
     }
 }
 
+fn float_const(value: &str) -> Box<Expression> {
+    Expression::constant(&value.to_string())
+}
+
+// lhs op rhs, through the int128 macros for 128-bit integers
+fn int_op<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: BinOpType,
+    lhs: Box<Expression>,
+    rhs: Box<Expression>,
+    ty: &Ty<'tcx>,
+) -> Box<Expression> {
+    if int128::is_int128(ty) {
+        let context = &mut fn_cx.ongoing_codegen.context;
+        Box::new(int128::handle_binary_op(context, op, *lhs, *rhs, ty, ty))
+    } else {
+        Box::new(Expression::BinaryOp { op, lhs, rhs })
+    }
+}
+
+// lhs op rhs, wrapping around on overflow
+fn wrapping_op<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: BinOpType,
+    lhs: Expression,
+    rhs: Expression,
+    ty: &Ty<'tcx>,
+) -> Box<Expression> {
+    if int128::is_int128(ty) {
+        let context = &mut fn_cx.ongoing_codegen.context;
+        Box::new(int128::wrapping(context, op, lhs, rhs, ty))
+    } else {
+        let c_ty = fn_cx.rust_to_c_type(ty);
+        Box::new(Expression::wrapping(op, lhs, rhs, &c_ty))
+    }
+}
+
+// an integer constant of type ty
+fn int_const<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    value: i128,
+    ty: &Ty<'tcx>,
+) -> Box<Expression> {
+    if int128::is_int128(ty) {
+        let c_ty = fn_cx.rust_to_c_type(ty);
+        let context = &mut fn_cx.ongoing_codegen.context;
+        Box::new(int128::literal(context, value as u128, &c_ty))
+    } else {
+        Box::new(Expression::const_int(value))
+    }
+}
+
 fn signed_add<'tcx, 'ccx>(
     fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     fn_name: &String,
     c_struct: &CStructDef,
     return_type: CType,
+    ty: &Ty<'tcx>,
 ) -> CFunction {
     let field = c_struct.get_field(0);
     let sum_type = field.get_type();
     let mut c_fn = CFunction::new(fn_name.clone(), return_type.clone());
-    let (max_int_str, min_int_str) = extremum_val_of_type(fn_cx, sum_type);
     c_fn.add_signature_var(CVarDef::new(1, "first".to_string(), sum_type.clone()));
     c_fn.add_signature_var(CVarDef::new(2, "second".to_string(), sum_type.clone()));
     c_fn.add_var_decl(CVarDecl::new(
@@ -206,28 +394,23 @@ fn signed_add<'tcx, 'ccx>(
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(3, "sum".to_string(), sum_type.clone()),
-        Some(Expression::vari(1) + Expression::vari(2)),
-    ));
-    // (second > 0 && first > INT{bit_width}_MAX - second)
+        Some(wrapping_op(
+            fn_cx,
+            BinOpType::Add,
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            ty,
+        )),
+    ));
+    // the sum has a different sign than both operands: ((first ^ sum) & (second ^ sum)) < 0
     c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(4, "overflow_a".to_string(), CType::Bool),
-        Some(
-            Expression::vari(2).gt(Expression::constant(&"0".to_string()))
-                & Expression::vari(1).gt(Expression::constant(&max_int_str) - Expression::vari(2)),
-        ),
-    ));
-    // (second < 0 && first < INT{bit_width}_MIN - second)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(5, "overflow_b".to_string(), CType::Bool),
-        Some(
-            Expression::vari(2).lt(Expression::constant(&"0".to_string()))
-                & Expression::vari(1).lt(Expression::constant(&min_int_str) - Expression::vari(2)),
-        ),
-    ));
-    // (second > 0 && first > INT{bit_width}_MAX - second) | (second < 0 && first < INT{bit_width}_MIN - second)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(6, "overflow".to_string(), CType::Bool),
-        Some(Expression::vari(4) | Expression::vari(5)),
+        CVarDef::new(4, "overflow".to_string(), CType::Bool),
+        Some(sign_bit_set(
+            fn_cx,
+            xor(fn_cx, 1, 3, ty),
+            xor(fn_cx, 2, 3, ty),
+            ty,
+        )),
     ));
 
     let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
@@ -235,7 +418,7 @@ fn signed_add<'tcx, 'ccx>(
         &Expression::unbvari(0),
         Expression::strct(
             Expression::constant(&c_struct.get_name()),
-            vec![Expression::unbvari(3), Expression::unbvari(6)],
+            vec![Expression::unbvari(3), Expression::unbvari(4)],
         ),
     )));
 
@@ -246,11 +429,40 @@ fn signed_add<'tcx, 'ccx>(
     c_fn
 }
 
+// (first ^ second) of two locals
+fn xor<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    first: usize,
+    second: usize,
+    ty: &Ty<'tcx>,
+) -> Box<Expression> {
+    int_op(
+        fn_cx,
+        BinOpType::Xor,
+        Expression::vari(first),
+        Expression::vari(second),
+        ty,
+    )
+}
+
+// (lhs & rhs) < 0
+fn sign_bit_set<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    lhs: Box<Expression>,
+    rhs: Box<Expression>,
+    ty: &Ty<'tcx>,
+) -> Box<Expression> {
+    let both = int_op(fn_cx, BinOpType::And, lhs, rhs, ty);
+    let zero = int_const(fn_cx, 0, ty);
+    int_op(fn_cx, BinOpType::Lt, both, zero, ty)
+}
+
 fn unsigned_add<'tcx, 'ccx>(
-    _fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     fn_name: &String,
     c_struct: &CStructDef,
     return_type: CType,
+    ty: &Ty<'tcx>,
 ) -> CFunction {
     let field = c_struct.get_field(0);
     let sum_type = field.get_type();
@@ -264,11 +476,23 @@ fn unsigned_add<'tcx, 'ccx>(
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(3, "sum".to_string(), sum_type.clone()),
-        Some(Expression::vari(1) + Expression::vari(2)),
+        Some(wrapping_op(
+            fn_cx,
+            BinOpType::Add,
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            ty,
+        )),
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(4, "overflow".to_string(), CType::Bool),
-        Some(Expression::vari(3).lt(Expression::vari(1))),
+        Some(int_op(
+            fn_cx,
+            BinOpType::Lt,
+            Expression::vari(3),
+            Expression::vari(1),
+            ty,
+        )),
     ));
 
     let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
@@ -291,10 +515,10 @@ fn signed_sub<'tcx, 'ccx>(
     fn_name: &String,
     c_struct: &CStructDef,
     return_type: CType,
+    ty: &Ty<'tcx>,
 ) -> CFunction {
     let field = c_struct.get_field(0);
     let diff_type = field.get_type();
-    let (max_int_str, min_int_str) = extremum_val_of_type(fn_cx, diff_type);
     let mut c_fn = CFunction::new(fn_name.clone(), return_type.clone());
     c_fn.add_signature_var(CVarDef::new(1, "first".to_string(), diff_type.clone()));
     c_fn.add_signature_var(CVarDef::new(2, "second".to_string(), diff_type.clone()));
@@ -304,28 +528,23 @@ fn signed_sub<'tcx, 'ccx>(
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(3, "difference".to_string(), diff_type.clone()),
-        Some(Expression::vari(1) - Expression::vari(2)),
-    ));
-    //second > 0 && first < INT{bit_width}_MIN + second
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(4, "overflow_a".to_string(), CType::Bool),
-        Some(
-            Expression::vari(2).gt(Expression::constant(&"0".to_string()))
-                & Expression::vari(1).lt(Expression::constant(&min_int_str) + Expression::vari(2)),
-        ),
-    ));
-    // second < 0 && first > INT{bit_width}_MAX + second
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(5, "overflow_b".to_string(), CType::Bool),
-        Some(
-            Expression::vari(2).lt(Expression::constant(&"0".to_string()))
-                & Expression::vari(1).gt(Expression::constant(&max_int_str) + Expression::vari(2)),
-        ),
-    ));
-    //((second > 0 && first < INT{bit_width}_MIN + second) || (second < 0 && first > INT{bit_width}_MAX + second))
+        Some(wrapping_op(
+            fn_cx,
+            BinOpType::Sub,
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            ty,
+        )),
+    ));
+    // the operands have different signs, and the difference the sign of the second: ((first ^ second) & (first ^ difference)) < 0
     c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(6, "overflow".to_string(), CType::Bool),
-        Some(Expression::vari(4) | Expression::vari(5)),
+        CVarDef::new(4, "overflow".to_string(), CType::Bool),
+        Some(sign_bit_set(
+            fn_cx,
+            xor(fn_cx, 1, 2, ty),
+            xor(fn_cx, 1, 3, ty),
+            ty,
+        )),
     ));
 
     let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
@@ -333,7 +552,7 @@ fn signed_sub<'tcx, 'ccx>(
         &Expression::unbvari(0),
         Expression::strct(
             Expression::constant(&c_struct.get_name()),
-            vec![Expression::unbvari(3), Expression::unbvari(6)],
+            vec![Expression::unbvari(3), Expression::unbvari(4)],
         ),
     )));
     bb.push(Statement::from_expression(Expression::Return {
@@ -344,10 +563,11 @@ fn signed_sub<'tcx, 'ccx>(
 }
 
 fn unsigned_sub<'tcx, 'ccx>(
-    _fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     fn_name: &String,
     c_struct: &CStructDef,
     return_type: CType,
+    ty: &Ty<'tcx>,
 ) -> CFunction {
     let field = c_struct.get_field(0);
     let difference_type = field.get_type();
@@ -369,11 +589,23 @@ fn unsigned_sub<'tcx, 'ccx>(
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(3, "difference".to_string(), difference_type.clone()),
-        Some(Expression::vari(1) - Expression::vari(2)),
+        Some(wrapping_op(
+            fn_cx,
+            BinOpType::Sub,
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            ty,
+        )),
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(4, "underflow".to_string(), CType::Bool),
-        Some(Expression::vari(1).lt(Expression::vari(2))),
+        Some(int_op(
+            fn_cx,
+            BinOpType::Lt,
+            Expression::vari(1),
+            Expression::vari(2),
+            ty,
+        )),
     ));
 
     let mut bb = BasicBlock::new(BasicBlockIdentifier(1));
@@ -396,11 +628,12 @@ fn signed_mul<'tcx, 'ccx>(
     fn_name: &String,
     c_struct: &CStructDef,
     return_type: CType,
+    ty: &Ty<'tcx>,
 ) -> CFunction {
     let field = c_struct.get_field(0);
     let product_type = field.get_type();
     let mut c_fn = CFunction::new(fn_name.clone(), return_type.clone());
-    let (max_int_str, min_int_str) = extremum_val_of_type(fn_cx, product_type);
+    let (_, min_int) = extremum_val_of_type(fn_cx, product_type);
 
     c_fn.add_signature_var(CVarDef::new(1, "first".to_string(), product_type.clone()));
     c_fn.add_signature_var(CVarDef::new(2, "second".to_string(), product_type.clone()));
@@ -410,118 +643,83 @@ fn signed_mul<'tcx, 'ccx>(
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(3, "product".to_string(), product_type.clone()),
-        Some(Expression::vari(1) * Expression::vari(2)),
+        Some(wrapping_op(
+            fn_cx,
+            BinOpType::Mul,
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            ty,
+        )),
     ));
     c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(4, "is_zero".to_string(), CType::Bool),
-        Some(
-            Expression::vari(1).equ(Expression::constant(&"0".to_string()))
-                | Expression::vari(2).equ(Expression::constant(&"0".to_string())),
-        ),
-    ));
-
-    // (a == -1 && b == INT{}_MIN)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(5, "overflow_a".to_string(), CType::Bool),
-        None,
-    ));
-    // (b == -1 && a == INT{}_MIN)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(6, "overflow_b".to_string(), CType::Bool),
-        None,
-    ));
-    // (a > 0 && b > 0 && a > INT{}_MAX / b)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(7, "overflow_c".to_string(), CType::Bool),
-        None,
-    ));
-    // (a > 0 && b < 0 && b < INT{}_MIN / a)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(8, "overflow_d".to_string(), CType::Bool),
-        None,
-    ));
-    // (a < 0 && b > 0 && a < INT{}_MIN / b)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(9, "overflow_e".to_string(), CType::Bool),
-        None,
-    ));
-    // (a < 0 && b < 0 && a < INT{}_MAX / b)
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(10, "overflow_f".to_string(), CType::Bool),
+        CVarDef::new(4, "overflow".to_string(), CType::Bool),
         None,
     ));
-    c_fn.add_var_decl(CVarDecl::new(
-        CVarDef::new(11, "overflow".to_string(), CType::Bool),
-        Some(Box::new(Expression::const_int(0))),
-    ));
+
+    // dividing the product by first is only defined if first is neither 0 nor -1
+    let zero = int_const(fn_cx, 0, ty);
+    let minus_one = int_const(fn_cx, -1, ty);
     let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
     bb0.push(Statement::from_expression(Expression::SwitchJump {
-        value: Expression::vari(4),
+        value: int_op(fn_cx, BinOpType::Eq, Expression::vari(1), zero, ty)
+            | int_op(
+                fn_cx,
+                BinOpType::Eq,
+                Expression::vari(1),
+                minus_one.clone(),
+                ty,
+            ),
         cases: vec![(Box::new(Expression::const_int(0)), BasicBlockIdentifier(1))],
         default: BasicBlockIdentifier(2),
     }));
 
+    // product / first != second
     let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
-    bb1.push(Statement::from_expression(Expression::unbvari(5).assign(
-        Expression::vari(1).equ(Expression::constant(&"-1".to_string()))
-            & Expression::vari(2).equ(Expression::constant(&min_int_str)),
-    )));
-    bb1.push(Statement::from_expression(Expression::unbvari(6).assign(
-        Expression::vari(2).equ(Expression::constant(&"-1".to_string()))
-            & Expression::vari(1).equ(Expression::constant(&min_int_str)),
-    )));
-    bb1.push(Statement::from_expression(Expression::unbvari(7).assign(
-        Expression::vari(1).gt(Expression::constant(&"0".to_string()))
-            & Expression::vari(2).gt(Expression::constant(&"0".to_string()))
-            & Expression::vari(1).gt(Expression::constant(&max_int_str) / Expression::vari(2)),
-    )));
-    bb1.push(Statement::from_expression(Expression::unbvari(8).assign(
-        Expression::vari(1).gt(Expression::constant(&"0".to_string()))
-            & Expression::vari(2).lt(Expression::constant(&"0".to_string()))
-            & Expression::vari(3).lt(Expression::constant(&min_int_str) / Expression::vari(1)),
-    )));
-    bb1.push(Statement::from_expression(Expression::unbvari(9).assign(
-        Expression::vari(1).lt(Expression::constant(&"0".to_string()))
-            & Expression::vari(2).gt(Expression::constant(&"0".to_string()))
-            & Expression::vari(1).lt(Expression::constant(&min_int_str) / Expression::vari(2)),
-    )));
-    bb1.push(Statement::from_expression(Expression::unbvari(10).assign(
-        Expression::vari(1).lt(Expression::constant(&"0".to_string()))
-            & Expression::vari(2).lt(Expression::constant(&"0".to_string()))
-            & Expression::vari(1).lt(Expression::constant(&max_int_str) / Expression::vari(2)),
-    )));
-    bb1.push(Statement::from_expression(Expression::unbvari(11).assign(
-        Expression::vari(5)
-            | Expression::vari(6)
-            | Expression::vari(7)
-            | Expression::vari(8)
-            | Expression::vari(9)
-            | Expression::vari(10),
-    )));
+    bb1.push(Statement::from_expression(
+        Expression::unbvari(4).assign(quotient_differs(fn_cx, ty)),
+    ));
+    bb1.push(Statement::from_expression(Expression::Goto {
+        target: BasicBlockIdentifier(3),
+    }));
 
+    // (first == -1 && second == INT{}_MIN)
     let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
-    bb2.push(Statement::from_expression(Expression::assign(
+    bb2.push(Statement::from_expression(Expression::unbvari(4).assign(
+        int_op(fn_cx, BinOpType::Eq, Expression::vari(1), minus_one, ty)
+            & int_op(
+                fn_cx,
+                BinOpType::Eq,
+                Expression::vari(2),
+                Box::new(min_int),
+                ty,
+            ),
+    )));
+
+    let mut bb3 = BasicBlock::new(BasicBlockIdentifier(3));
+    bb3.push(Statement::from_expression(Expression::assign(
         &Expression::unbvari(0),
         Expression::strct(
             Expression::constant(&c_struct.get_name()),
-            vec![Expression::unbvari(3), Expression::unbvari(11)],
+            vec![Expression::unbvari(3), Expression::unbvari(4)],
         ),
     )));
-    bb2.push(Statement::from_expression(Expression::Return {
+    bb3.push(Statement::from_expression(Expression::Return {
         value: Expression::vari(0),
     }));
 
     c_fn.push_bb(bb0);
     c_fn.push_bb(bb1);
     c_fn.push_bb(bb2);
+    c_fn.push_bb(bb3);
     c_fn
 }
 
 fn unsigned_mul<'tcx, 'ccx>(
-    _fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     fn_name: &String,
     c_struct: &CStructDef,
     return_type: CType,
+    ty: &Ty<'tcx>,
 ) -> CFunction {
     let field = c_struct.get_field(0);
     let product_type = field.get_type();
@@ -535,11 +733,18 @@ fn unsigned_mul<'tcx, 'ccx>(
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(3, "product".to_string(), product_type.clone()),
-        Some(Expression::vari(1) * Expression::vari(2)),
-    ));
+        Some(wrapping_op(
+            fn_cx,
+            BinOpType::Mul,
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            ty,
+        )),
+    ));
+    let zero = int_const(fn_cx, 0, ty);
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(4, "is_zero".to_string(), CType::Bool),
-        Some(Expression::vari(1).equ(Expression::constant(&"0".to_string()))),
+        Some(int_op(fn_cx, BinOpType::Eq, Expression::vari(1), zero, ty)),
     ));
     c_fn.add_var_decl(CVarDecl::new(
         CVarDef::new(5, "overflow".to_string(), CType::Bool),
@@ -552,9 +757,9 @@ fn unsigned_mul<'tcx, 'ccx>(
         default: BasicBlockIdentifier(2),
     }));
     let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
-    bb1.push(Statement::from_expression(Expression::unbvari(5).assign(
-        Expression::vari(3) / (Expression::vari(1)).neq(Expression::vari(2)),
-    )));
+    bb1.push(Statement::from_expression(
+        Expression::unbvari(5).assign(quotient_differs(fn_cx, ty)),
+    ));
 
     let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
     bb2.push(Statement::from_expression(Expression::assign(
@@ -572,3 +777,18 @@ fn unsigned_mul<'tcx, 'ccx>(
     c_fn.push_bb(bb2);
     c_fn
 }
+
+// product / first != second
+fn quotient_differs<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ty: &Ty<'tcx>,
+) -> Box<Expression> {
+    let quotient = int_op(
+        fn_cx,
+        BinOpType::Div,
+        Expression::vari(3),
+        Expression::vari(1),
+        ty,
+    );
+    int_op(fn_cx, BinOpType::Ne, quotient, Expression::vari(2), ty)
+}
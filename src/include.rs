@@ -6,6 +6,8 @@ use crate::crepr::Representable;
 pub struct Include {
     pub name: String,
     pub is_system: bool,
+    // preprocessor condition for headers that are not available everywhere
+    pub condition: Option<String>,
 }
 
 impl Representable for Include {
@@ -14,11 +16,18 @@ impl Representable for Include {
         f: &mut (dyn fmt::Write),
         _context: &mut crate::crepr::RepresentationContext,
     ) -> fmt::Result {
+        if let Some(condition) = &self.condition {
+            write!(f, "#if {}\n", condition)?;
+        }
         if self.is_system {
-            write!(f, "#include <{}>", self.name)
+            write!(f, "#include <{}>", self.name)?;
         } else {
-            write!(f, "#include \"{}\"", self.name)
+            write!(f, "#include \"{}\"", self.name)?;
+        }
+        if self.condition.is_some() {
+            write!(f, "\n#endif")?;
         }
+        Ok(())
     }
 }
 
@@ -30,6 +39,18 @@ impl Debug for Include {
 
 impl Include {
     pub fn new(name: String, is_system: bool) -> Self {
-        Self { name, is_system }
+        Self {
+            name,
+            is_system,
+            condition: None,
+        }
+    }
+    /// Includes the header only if the preprocessor `condition` holds
+    pub fn new_conditional(name: String, is_system: bool, condition: String) -> Self {
+        Self {
+            name,
+            is_system,
+            condition: Some(condition),
+        }
     }
 }
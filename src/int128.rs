@@ -0,0 +1,910 @@
+use rustc_middle::ty::{IntTy, Ty, TyKind, UintTy};
+
+use crate::{
+    base::Context,
+    bb::{BasicBlock, BasicBlockIdentifier},
+    definition::{CVarDecl, CVarDef},
+    expression::{BinOpType, Expression, UnaryOpType, VariableAccess},
+    function::CFunction,
+    header::CDefine,
+    stmt::Statement,
+    structure::{CComposite, CStructDef},
+    ty::{CIntTy, CType, CUIntTy},
+};
+
+// compilers with a native 128-bit integer type, everything else uses the struct based implementation.
+// Defining CODEGENC_SOFT_INT128 forces the struct based implementation
+const NATIVE_CONDITION: &str = "defined(__SIZEOF_INT128__) && !defined(CODEGENC_SOFT_INT128)";
+
+pub const I128_NAME: &str = "codegenc_i128";
+pub const U128_NAME: &str = "codegenc_u128";
+
+// both signednesses share the software representation, the operations decide how the bits are interpreted
+const SOFT_NAME: &str = "codegenc_int128_soft";
+const SOFT_PREFIX: &str = "codegenc_int128_soft_";
+
+pub fn is_int128(ty: &Ty) -> bool {
+    matches!(
+        ty.kind(),
+        TyKind::Int(IntTy::I128) | TyKind::Uint(UintTy::U128)
+    )
+}
+
+fn call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FnCall {
+        function: Expression::constant(&function.to_string()),
+        args,
+    }
+}
+
+fn type_arg(ty: &CType) -> Expression {
+    Expression::Constant {
+        value: format!("{:?}", ty),
+    }
+}
+
+fn add_define(context: &mut Context, name: String, native: String, soft: String) {
+    if !context.has_define_with_name(&name) {
+        context.get_mut_defines().push(CDefine::new_conditional(
+            name,
+            NATIVE_CONDITION.to_string(),
+            native,
+            soft,
+        ));
+    }
+}
+
+// #if defined(__SIZEOF_INT128__)
+// #define codegenc_i128 __int128
+// #else
+// #define codegenc_i128 codegenc_int128_soft
+// #endif
+/// Declares the 128-bit integer types, along with the struct used when the compiler has none
+pub fn add_int128_types(context: &mut Context) {
+    if context.has_define_with_name(&U128_NAME.to_string()) {
+        return;
+    }
+
+    context.add_composite(&CComposite::Struct(CStructDef {
+        name: SOFT_NAME.to_string(),
+        fields: vec![
+            CVarDef::new(0, "lo".to_string(), u64_ty()),
+            CVarDef::new(1, "hi".to_string(), u64_ty()),
+        ],
+    }));
+
+    add_define(
+        context,
+        I128_NAME.to_string(),
+        "__int128".to_string(),
+        SOFT_NAME.to_string(),
+    );
+    add_define(
+        context,
+        U128_NAME.to_string(),
+        "unsigned __int128".to_string(),
+        SOFT_NAME.to_string(),
+    );
+}
+
+/// 128-bit integer built from its two 64-bit halves
+pub fn from_halves(
+    context: &mut Context,
+    ty: &CType,
+    hi: Expression,
+    lo: Expression,
+) -> Expression {
+    add_define(
+        context,
+        "codegenc_int128_lit(T, hi, lo)".to_string(),
+        "((T)(((unsigned __int128)(hi) << 64) | (lo)))".to_string(),
+        "((T){ (lo), (hi) })".to_string(),
+    );
+
+    call("codegenc_int128_lit", vec![type_arg(ty), hi, lo])
+}
+
+/// 128-bit integer literal, built from its two 64-bit halves
+pub fn literal(context: &mut Context, value: u128, ty: &CType) -> Expression {
+    from_halves(
+        context,
+        ty,
+        Expression::Constant {
+            value: format!("0x{:x}u", (value >> 64) as u64),
+        },
+        Expression::Constant {
+            value: format!("0x{:x}u", value as u64),
+        },
+    )
+}
+
+/// The largest value of a 128-bit integer type
+pub fn max(context: &mut Context, ty: &CType) -> Expression {
+    let value = match ty {
+        CType::Int(_) => i128::MAX as u128,
+        _ => u128::MAX,
+    };
+    literal(context, value, ty)
+}
+
+/// The smallest value of a 128-bit integer type
+pub fn min(context: &mut Context, ty: &CType) -> Expression {
+    let value = match ty {
+        CType::Int(_) => i128::MIN as u128,
+        _ => 0,
+    };
+    literal(context, value, ty)
+}
+
+/// The high 64 bits of a 128-bit integer, as a uint64_t
+pub fn high_half(context: &mut Context, value: Expression) -> Expression {
+    add_define(
+        context,
+        "codegenc_int128_hi(x)".to_string(),
+        "((uint64_t)((unsigned __int128)(x) >> 64))".to_string(),
+        "((x).hi)".to_string(),
+    );
+    call("codegenc_int128_hi", vec![value])
+}
+
+/// The low 64 bits of a 128-bit integer, as a uint64_t
+pub fn low_half(context: &mut Context, value: Expression) -> Expression {
+    add_define(
+        context,
+        "codegenc_int128_lo(x)".to_string(),
+        "((uint64_t)(x))".to_string(),
+        "((x).lo)".to_string(),
+    );
+    call("codegenc_int128_lo", vec![value])
+}
+
+/// Lowers a binary operation with at least one 128-bit operand
+pub fn handle_binary_op<'tcx>(
+    context: &mut Context,
+    op: BinOpType,
+    lhs: Expression,
+    rhs: Expression,
+    lhs_ty: &Ty<'tcx>,
+    rhs_ty: &Ty<'tcx>,
+) -> Expression {
+    let signedness = if lhs_ty.is_signed() { "i" } else { "u" };
+    let (name, native, soft) = match op {
+        BinOpType::Add
+        | BinOpType::Sub
+        | BinOpType::Mul
+        | BinOpType::And
+        | BinOpType::Or
+        | BinOpType::Xor => (
+            format!("codegenc_int128_{op}"),
+            format!("((a) {} (b))", op.default_repr_str()),
+            format!("{}(a, b)", soft_fn(context, &op.to_string())),
+        ),
+        // guarded like header::handle_div_rem, a zero divisor gives 0 and MIN / -1 wraps around
+        BinOpType::Div | BinOpType::Mod => (
+            format!("codegenc_{signedness}128_{op}"),
+            match (&op, signedness) {
+                (BinOpType::Div, "i") => "((b) == 0 ? 0 : (b) == -1 ? (__int128)(0 - (unsigned __int128)(a)) : (a) / (b))".to_string(),
+                (_, "i") => "((b) == 0 || (b) == -1 ? 0 : (a) % (b))".to_string(),
+                _ => format!("((b) == 0 ? 0 : (a) {} (b))", op.default_repr_str()),
+            },
+            format!(
+                "{}(a, b, {})",
+                soft_fn(context, &format!("divrem_{signedness}")),
+                matches!(op, BinOpType::Mod)
+            ),
+        ),
+        BinOpType::Shl | BinOpType::Shr => {
+            // the soft shifts take the amount as a uint32_t
+            let rhs = if is_int128(rhs_ty) {
+                truncate(context, rhs, &CType::UInt(CUIntTy::UInt32))
+            } else {
+                rhs
+            };
+            if !is_int128(lhs_ty) {
+                return Expression::BinaryOp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                };
+            }
+
+            let (name, soft) = match (op, signedness) {
+                (BinOpType::Shl, _) => ("codegenc_int128_shl".to_string(), "shl"),
+                (_, "i") => ("codegenc_i128_shr".to_string(), "ashr"),
+                _ => ("codegenc_u128_shr".to_string(), "lshr"),
+            };
+            let soft = format!("{}(a, (uint32_t)(n))", soft_fn(context, soft));
+            let native = format!("((a) {} (n))", op.default_repr_str());
+            add_define(context, format!("{name}(a, n)"), native, soft);
+            return call(&name, vec![lhs, rhs]);
+        }
+        BinOpType::Eq => (
+            format!("codegenc_int128_{op}"),
+            "((a) == (b))".to_string(),
+            "((a).lo == (b).lo && (a).hi == (b).hi)".to_string(),
+        ),
+        BinOpType::Ne => (
+            format!("codegenc_int128_{op}"),
+            "((a) != (b))".to_string(),
+            "((a).lo != (b).lo || (a).hi != (b).hi)".to_string(),
+        ),
+        BinOpType::Lt | BinOpType::Le | BinOpType::Gt | BinOpType::Ge => (
+            format!("codegenc_{signedness}128_{op}"),
+            format!("((a) {} (b))", op.default_repr_str()),
+            format!(
+                "({}(a, b) {} 0)",
+                soft_fn(context, &format!("cmp_{signedness}")),
+                op.default_repr_str()
+            ),
+        ),
+        _ => panic!("Unhandled 128-bit integer operation: {:?}", op),
+    };
+
+    add_define(context, format!("{name}(a, b)"), native, soft);
+    call(&name, vec![lhs, rhs])
+}
+
+/// Lowers negation and bitwise not of a 128-bit integer
+pub fn handle_unary_op(context: &mut Context, op: UnaryOpType, val: Expression) -> Expression {
+    let (name, native) = match op {
+        UnaryOpType::Neg => ("neg", "(-(a))"),
+        UnaryOpType::BitNot => ("not", "(~(a))"),
+        _ => panic!("Unhandled 128-bit integer operation: {:?}", op),
+    };
+
+    let soft = format!("{}(a)", soft_fn(context, name));
+    let macro_name = format!("codegenc_int128_{name}");
+    add_define(
+        context,
+        format!("{macro_name}(a)"),
+        native.to_string(),
+        soft,
+    );
+    call(&macro_name, vec![val])
+}
+
+/// Adds, subtracts or multiplies 128-bit integers, wrapping around on overflow.
+/// Overflowing a native signed integer is undefined, so signed operands are computed as unsigned ones
+pub fn wrapping<'tcx>(
+    context: &mut Context,
+    op: BinOpType,
+    lhs: Expression,
+    rhs: Expression,
+    ty: &Ty<'tcx>,
+) -> Expression {
+    if !ty.is_signed() {
+        return handle_binary_op(context, op, lhs, rhs, ty, ty);
+    }
+
+    let unsigned = CType::UInt(CUIntTy::UInt128);
+    let lhs = cast(context, lhs, &unsigned);
+    let rhs = cast(context, rhs, &unsigned);
+    let value = handle_binary_op(context, op, lhs, rhs, ty, ty);
+    cast(context, value, &CType::Int(CIntTy::Int128))
+}
+
+// casts between the 128-bit types keep the bits as is
+fn cast(context: &mut Context, value: Expression, target: &CType) -> Expression {
+    add_define(
+        context,
+        "codegenc_int128_cast(T, x)".to_string(),
+        "((T)(x))".to_string(),
+        "(x)".to_string(),
+    );
+    call("codegenc_int128_cast", vec![type_arg(target), value])
+}
+
+/// Converts a float of type `float_ty` to the 128-bit integer type `target`, the value has to be in range.
+/// Without a native type the halves are converted separately, they are exact in every float type
+pub fn from_float(
+    context: &mut Context,
+    value: Expression,
+    float_ty: &CType,
+    target: &CType,
+) -> Expression {
+    add_define(
+        context,
+        "codegenc_int128_from_float_u(T, F, x)".to_string(),
+        "((T)(x))".to_string(),
+        "((T){ (uint64_t)((x) - (F)(uint64_t)((x) / 0x1p64) * 0x1p64), (uint64_t)((x) / 0x1p64) })"
+            .to_string(),
+    );
+
+    let name = match target {
+        CType::Int(_) => {
+            // negative values are converted as their magnitude
+            let soft = format!(
+                "((x) < 0 ? {}(codegenc_int128_from_float_u(T, F, -(x))) : codegenc_int128_from_float_u(T, F, x))",
+                soft_fn(context, "neg")
+            );
+            add_define(
+                context,
+                "codegenc_int128_from_float_i(T, F, x)".to_string(),
+                "((T)(x))".to_string(),
+                soft,
+            );
+            "codegenc_int128_from_float_i"
+        }
+        _ => "codegenc_int128_from_float_u",
+    };
+
+    call(name, vec![type_arg(target), type_arg(float_ty), value])
+}
+
+/// The low bits of a 128-bit integer, as a smaller integer type
+pub fn truncate(context: &mut Context, value: Expression, target: &CType) -> Expression {
+    add_define(
+        context,
+        "codegenc_int128_to(T, x)".to_string(),
+        "((T)(x))".to_string(),
+        "((T)(x).lo)".to_string(),
+    );
+    call("codegenc_int128_to", vec![type_arg(target), value])
+}
+
+/// Lowers an integer cast from or to a 128-bit integer
+pub fn handle_int_cast<'tcx>(
+    context: &mut Context,
+    value: Expression,
+    source_ty: &Ty<'tcx>,
+    target_ty: &Ty<'tcx>,
+    target: &CType,
+) -> Expression {
+    match (is_int128(source_ty), is_int128(target_ty)) {
+        (true, true) => cast(context, value, target),
+
+        (true, false) => truncate(context, value, target),
+
+        // smaller integers are sign or zero extended depending on their own signedness
+        (false, true) => {
+            let (name, soft) = if source_ty.is_signed() {
+                (
+                    "codegenc_int128_from_i",
+                    "((T){ (uint64_t)(int64_t)(x), (uint64_t)-((int64_t)(x) < 0) })",
+                )
+            } else {
+                ("codegenc_int128_from_u", "((T){ (uint64_t)(x), 0 })")
+            };
+            add_define(
+                context,
+                format!("{name}(T, x)"),
+                "((T)(x))".to_string(),
+                soft.to_string(),
+            );
+            call(name, vec![type_arg(target), value])
+        }
+
+        (false, false) => panic!("Cast between integers narrower than 128 bits"),
+    }
+}
+
+fn u64_ty() -> CType {
+    CType::UInt(CUIntTy::UInt64)
+}
+
+fn soft_ty() -> CType {
+    CType::Opaque(SOFT_NAME.to_string())
+}
+
+fn lo(local: usize) -> Expression {
+    Expression::Variable {
+        local,
+        access: vec![VariableAccess::Field {
+            name: "lo".to_string(),
+        }],
+    }
+}
+
+fn hi(local: usize) -> Expression {
+    Expression::Variable {
+        local,
+        access: vec![VariableAccess::Field {
+            name: "hi".to_string(),
+        }],
+    }
+}
+
+fn signed(value: Expression) -> Box<Expression> {
+    Box::new(Expression::Cast {
+        ty: CType::Int(CIntTy::Int64),
+        value: Box::new(value),
+    })
+}
+
+fn unsigned(value: Box<Expression>) -> Box<Expression> {
+    Box::new(Expression::Cast {
+        ty: u64_ty(),
+        value,
+    })
+}
+
+fn int(value: i128) -> Box<Expression> {
+    Box::new(Expression::const_int(value))
+}
+
+fn soft_literal(lo: i128, hi: i128) -> Box<Expression> {
+    Expression::strct(
+        Expression::constant(&SOFT_NAME.to_string()),
+        vec![Expression::const_int(lo), Expression::const_int(hi)],
+    )
+}
+
+fn stmt(expression: Expression) -> Statement {
+    Statement::from_expression(expression)
+}
+
+fn ret(value: Box<Expression>) -> Statement {
+    stmt(Expression::Return { value })
+}
+
+fn jump_unless(cond: Box<Expression>, target: usize, otherwise: usize) -> Statement {
+    stmt(Expression::SwitchJump {
+        value: cond,
+        cases: vec![(int(0), BasicBlockIdentifier(target))],
+        default: BasicBlockIdentifier(otherwise),
+    })
+}
+
+/// Name of the software implementation of `op`, which is added to the header functions along with its dependencies
+fn soft_fn(context: &mut Context, op: &str) -> String {
+    let name = format!("{SOFT_PREFIX}{op}");
+    if context.exists_header_fn_with_name(&name) {
+        return name;
+    }
+
+    let dependencies: &[&str] = match op {
+        "divrem_u" => &["shl", "lshr", "cmp_u", "sub", "or"],
+        "divrem_i" => &["neg", "divrem_u"],
+        _ => &[],
+    };
+    for dependency in dependencies {
+        soft_fn(context, dependency);
+    }
+
+    let c_fn = match op {
+        "add" | "sub" => add_sub_fn(&name, op == "add"),
+        "mul" => mul_fn(&name),
+        "and" | "or" | "xor" => bitwise_fn(&name, op),
+        "not" | "neg" => negate_fn(&name, op == "neg"),
+        "shl" | "lshr" | "ashr" => shift_fn(&name, op),
+        "cmp_u" | "cmp_i" => cmp_fn(&name, op == "cmp_i"),
+        "divrem_u" => udivrem_fn(&name),
+        "divrem_i" => idivrem_fn(&name),
+        _ => panic!("Unknown 128-bit integer operation: {}", op),
+    };
+
+    context.get_mut_header_functions().push(c_fn);
+    name
+}
+
+fn binary_fn(name: &str, ret_ty: CType) -> CFunction {
+    let mut c_fn = CFunction::new(name.to_string(), ret_ty);
+    c_fn.add_signature_var(CVarDef::new(0, "a".to_string(), soft_ty()));
+    c_fn.add_signature_var(CVarDef::new(1, "b".to_string(), soft_ty()));
+    c_fn
+}
+
+fn with_result(mut c_fn: CFunction, local: usize, statements: Vec<Statement>) -> CFunction {
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(local, "r".to_string(), soft_ty()),
+        None,
+    ));
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    for statement in statements {
+        bb.push(statement);
+    }
+    bb.push(ret(Expression::vari(local)));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+// r.lo = a.lo + b.lo; r.hi = a.hi + b.hi + (r.lo < a.lo);
+// r.lo = a.lo - b.lo; r.hi = a.hi - b.hi - (a.lo < b.lo);
+fn add_sub_fn(name: &str, add: bool) -> CFunction {
+    let statements = if add {
+        vec![
+            stmt(lo(2).assign(Box::new(lo(0)) + Box::new(lo(1)))),
+            stmt(hi(2).assign(Box::new(hi(0)) + Box::new(hi(1)) + lo(2).lt(Box::new(lo(0))))),
+        ]
+    } else {
+        vec![
+            stmt(lo(2).assign(Box::new(lo(0)) - Box::new(lo(1)))),
+            stmt(hi(2).assign(Box::new(hi(0)) - Box::new(hi(1)) - lo(0).lt(Box::new(lo(1))))),
+        ]
+    };
+
+    with_result(binary_fn(name, soft_ty()), 2, statements)
+}
+
+// the low halves are multiplied in 32-bit pieces, the high halves only contribute to the high half of the result
+fn mul_fn(name: &str) -> CFunction {
+    let mut c_fn = binary_fn(name, soft_ty());
+    let mask = || {
+        Box::new(Expression::Constant {
+            value: "0xffffffffu".to_string(),
+        })
+    };
+
+    // a0 = 3, a1 = 4, b0 = 5, b1 = 6
+    let pieces = [
+        ("a0", Box::new(lo(0)) & mask()),
+        ("a1", Box::new(lo(0)) >> int(32)),
+        ("b0", Box::new(lo(1)) & mask()),
+        ("b1", Box::new(lo(1)) >> int(32)),
+    ];
+    for (idx, (var, value)) in pieces.into_iter().enumerate() {
+        c_fn.add_var_decl(CVarDecl::new(
+            CVarDef::new(3 + idx, var.to_string(), u64_ty()),
+            Some(value),
+        ));
+    }
+
+    // p00 = 7, p01 = 8, p10 = 9, p11 = 10
+    let products = [("p00", 3, 5), ("p01", 3, 6), ("p10", 4, 5), ("p11", 4, 6)];
+    for (idx, (var, lhs, rhs)) in products.into_iter().enumerate() {
+        c_fn.add_var_decl(CVarDecl::new(
+            CVarDef::new(7 + idx, var.to_string(), u64_ty()),
+            Some(Expression::vari(lhs) * Expression::vari(rhs)),
+        ));
+    }
+
+    // mid = (p00 >> 32) + (p01 & 0xffffffff) + (p10 & 0xffffffff)
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(11, "mid".to_string(), u64_ty()),
+        Some(
+            (Expression::vari(7) >> int(32))
+                + (Expression::vari(8) & mask())
+                + (Expression::vari(9) & mask()),
+        ),
+    ));
+
+    let statements = vec![
+        stmt(lo(12).assign((Expression::vari(11) << int(32)) | (Expression::vari(7) & mask()))),
+        stmt(hi(12).assign(
+            Expression::vari(10)
+                + (Expression::vari(8) >> int(32))
+                + (Expression::vari(9) >> int(32))
+                + (Expression::vari(11) >> int(32))
+                + (Box::new(lo(0)) * Box::new(hi(1)))
+                + (Box::new(hi(0)) * Box::new(lo(1))),
+        )),
+    ];
+
+    with_result(c_fn, 12, statements)
+}
+
+fn bitwise_fn(name: &str, op: &str) -> CFunction {
+    let op = match op {
+        "and" => BinOpType::And,
+        "or" => BinOpType::Or,
+        _ => BinOpType::Xor,
+    };
+    let half = |half: fn(usize) -> Expression| Expression::BinaryOp {
+        op: op.clone(),
+        lhs: Box::new(half(0)),
+        rhs: Box::new(half(1)),
+    };
+
+    let statements = vec![
+        stmt(lo(2).assign(Box::new(half(lo)))),
+        stmt(hi(2).assign(Box::new(half(hi)))),
+    ];
+    with_result(binary_fn(name, soft_ty()), 2, statements)
+}
+
+// r.lo = ~a.lo; r.hi = ~a.hi;
+// r.lo = -a.lo; r.hi = -a.hi - (a.lo != 0);
+fn negate_fn(name: &str, neg: bool) -> CFunction {
+    let mut c_fn = CFunction::new(name.to_string(), soft_ty());
+    c_fn.add_signature_var(CVarDef::new(0, "a".to_string(), soft_ty()));
+
+    let op = if neg {
+        UnaryOpType::Neg
+    } else {
+        UnaryOpType::BitNot
+    };
+    let unary = |value: Expression| {
+        Box::new(Expression::UnaryOp {
+            op: op.clone(),
+            val: Box::new(value),
+        })
+    };
+
+    let high = if neg {
+        unary(hi(0)) - lo(0).neq(int(0))
+    } else {
+        unary(hi(0))
+    };
+    let statements = vec![stmt(lo(1).assign(unary(lo(0)))), stmt(hi(1).assign(high))];
+
+    with_result(c_fn, 1, statements)
+}
+
+// S shl(S a, uint32_t n) {
+//     n = n & 127;
+//     if (n == 0) return a;
+//     if (n < 64) { r.lo = a.lo << n; r.hi = (a.hi << n) | (a.lo >> (64 - n)); }
+//     else { r.lo = 0; r.hi = a.lo << (n - 64); }
+//     return r;
+// }
+fn shift_fn(name: &str, op: &str) -> CFunction {
+    let mut c_fn = CFunction::new(name.to_string(), soft_ty());
+    c_fn.add_signature_var(CVarDef::new(0, "a".to_string(), soft_ty()));
+    c_fn.add_signature_var(CVarDef::new(
+        1,
+        "n".to_string(),
+        CType::UInt(CUIntTy::UInt32),
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(2, "r".to_string(), soft_ty()),
+        None,
+    ));
+
+    let n = || Expression::vari(1);
+    let carry = || int(64) - n();
+    let big = || n() - int(64);
+
+    // (short shift, long shift), each assigning (lo, hi)
+    let (short, long) = match op {
+        "shl" => (
+            (
+                Box::new(lo(0)) << n(),
+                (Box::new(hi(0)) << n()) | (Box::new(lo(0)) >> carry()),
+            ),
+            (int(0), Box::new(lo(0)) << big()),
+        ),
+        "lshr" => (
+            (
+                (Box::new(lo(0)) >> n()) | (Box::new(hi(0)) << carry()),
+                Box::new(hi(0)) >> n(),
+            ),
+            (Box::new(hi(0)) >> big(), int(0)),
+        ),
+        _ => (
+            (
+                (Box::new(lo(0)) >> n()) | (Box::new(hi(0)) << carry()),
+                unsigned(signed(hi(0)) >> n()),
+            ),
+            (
+                unsigned(signed(hi(0)) >> big()),
+                unsigned(signed(hi(0)) >> int(63)),
+            ),
+        ),
+    };
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.push(stmt(
+        Expression::unbvari(1).assign(Expression::vari(1) & int(127)),
+    ));
+    bb0.push(jump_unless(Expression::vari(1).equ(int(0)), 1, 4));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(jump_unless(Expression::vari(1).lt(int(64)), 3, 2));
+
+    let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
+    bb2.push(stmt(lo(2).assign(short.0)));
+    bb2.push(stmt(hi(2).assign(short.1)));
+    bb2.push(ret(Expression::vari(2)));
+
+    let mut bb3 = BasicBlock::new(BasicBlockIdentifier(3));
+    bb3.push(stmt(lo(2).assign(long.0)));
+    bb3.push(stmt(hi(2).assign(long.1)));
+    bb3.push(ret(Expression::vari(2)));
+
+    let mut bb4 = BasicBlock::new(BasicBlockIdentifier(4));
+    bb4.push(ret(Expression::vari(0)));
+
+    for bb in [bb0, bb1, bb2, bb3, bb4] {
+        c_fn.push_bb(bb);
+    }
+    c_fn
+}
+
+// int32_t cmp(S a, S b) {
+//     if (a.hi < b.hi) return -1;
+//     if (a.hi > b.hi) return 1;
+//     if (a.lo < b.lo) return -1;
+//     return a.lo > b.lo;
+// }
+fn cmp_fn(name: &str, signed_cmp: bool) -> CFunction {
+    let mut c_fn = binary_fn(name, CType::Int(CIntTy::Int32));
+
+    let high = |local| {
+        if signed_cmp {
+            *signed(hi(local))
+        } else {
+            hi(local)
+        }
+    };
+    let if_ret = |cond: Box<Expression>, value: i128| {
+        stmt(Expression::If {
+            cond,
+            then: Box::new(Expression::Return { value: int(value) }),
+        })
+    };
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(if_ret(high(0).lt(Box::new(high(1))), -1));
+    bb.push(if_ret(high(0).gt(Box::new(high(1))), 1));
+    bb.push(if_ret(lo(0).lt(Box::new(lo(1))), -1));
+    bb.push(ret(lo(0).gt(Box::new(lo(1)))));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+// S divrem_u(S a, S b, bool rem) {
+//     S q = { 0, 0 }; S r = { 0, 0 }; uint32_t i = 128; S one = { 1, 0 }; S bit;
+// bb4:
+//     if ((b.lo == 0) & (b.hi == 0)) return q;  a zero divisor gives 0, as for the native types
+//     goto bb0;
+// bb0:
+//     if (i == 0) goto bb3;
+// bb1:
+//     i = i - 1;
+//     r = shl(r, 1);
+//     bit = lshr(a, i);
+//     r.lo = r.lo | (bit.lo & 1);
+//     if (cmp_u(r, b) < 0) goto bb0;
+// bb2:
+//     r = sub(r, b);
+//     q = or(q, shl(one, i));
+//     goto bb0;
+// bb3:
+//     if (rem) return r;
+//     return q;
+// }
+fn udivrem_fn(name: &str) -> CFunction {
+    let mut c_fn = binary_fn(name, soft_ty());
+    c_fn.add_signature_var(CVarDef::new(2, "rem".to_string(), CType::Bool));
+
+    let locals = [
+        ("q", soft_ty(), Some(soft_literal(0, 0))),
+        ("r", soft_ty(), Some(soft_literal(0, 0))),
+        ("i", CType::UInt(CUIntTy::UInt32), Some(int(128))),
+        ("one", soft_ty(), Some(soft_literal(1, 0))),
+        ("bit", soft_ty(), None),
+    ];
+    // q = 3, r = 4, i = 5, one = 6, bit = 7
+    for (idx, (var, ty, value)) in locals.into_iter().enumerate() {
+        c_fn.add_var_decl(CVarDecl::new(
+            CVarDef::new(3 + idx, var.to_string(), ty),
+            value,
+        ));
+    }
+    let soft =
+        |op: &str, args: Vec<Expression>| Box::new(call(&format!("{SOFT_PREFIX}{op}"), args));
+
+    let mut bb4 = BasicBlock::new(BasicBlockIdentifier(4));
+    bb4.push(stmt(Expression::If {
+        cond: lo(1).equ(int(0)) & hi(1).equ(int(0)),
+        then: Box::new(Expression::Return {
+            value: Expression::vari(3),
+        }),
+    }));
+    bb4.push(stmt(Expression::Goto {
+        target: BasicBlockIdentifier(0),
+    }));
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.push(jump_unless(Expression::vari(5).equ(int(0)), 1, 3));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(stmt(
+        Expression::unbvari(5).assign(Expression::vari(5) - int(1)),
+    ));
+    bb1.push(stmt(Expression::unbvari(4).assign(soft(
+        "shl",
+        vec![Expression::unbvari(4), Expression::const_int(1)],
+    ))));
+    bb1.push(stmt(Expression::unbvari(7).assign(soft(
+        "lshr",
+        vec![Expression::unbvari(0), Expression::unbvari(5)],
+    ))));
+    bb1.push(stmt(
+        lo(4).assign(Box::new(lo(4)) | (Box::new(lo(7)) & int(1))),
+    ));
+    let below = soft(
+        "cmp_u",
+        vec![Expression::unbvari(4), Expression::unbvari(1)],
+    )
+    .lt(int(0));
+    bb1.push(stmt(Expression::SwitchJump {
+        value: below,
+        cases: vec![(int(0), BasicBlockIdentifier(2))],
+        default: BasicBlockIdentifier(0),
+    }));
+
+    let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
+    bb2.push(stmt(Expression::unbvari(4).assign(soft(
+        "sub",
+        vec![Expression::unbvari(4), Expression::unbvari(1)],
+    ))));
+    bb2.push(stmt(Expression::unbvari(3).assign(soft(
+        "or",
+        vec![
+            Expression::unbvari(3),
+            *soft("shl", vec![Expression::unbvari(6), Expression::unbvari(5)]),
+        ],
+    ))));
+    bb2.push(stmt(Expression::Goto {
+        target: BasicBlockIdentifier(0),
+    }));
+
+    let mut bb3 = BasicBlock::new(BasicBlockIdentifier(3));
+    bb3.push(stmt(Expression::If {
+        cond: Expression::vari(2),
+        then: Box::new(Expression::Return {
+            value: Expression::vari(4),
+        }),
+    }));
+    bb3.push(ret(Expression::vari(3)));
+
+    for bb in [bb4, bb0, bb1, bb2, bb3] {
+        c_fn.push_bb(bb);
+    }
+    c_fn
+}
+
+// S divrem_i(S a, S b, bool rem) {
+//     bool na = (int64_t)a.hi < 0; bool nb = (int64_t)b.hi < 0; S q;
+//     if (na) a = neg(a);
+//     if (nb) b = neg(b);
+//     q = divrem_u(a, b, rem);
+//     if (na ^ (nb & !rem)) q = neg(q);  the remainder has the sign of the dividend
+//     return q;
+// }
+fn idivrem_fn(name: &str) -> CFunction {
+    let mut c_fn = binary_fn(name, soft_ty());
+    c_fn.add_signature_var(CVarDef::new(2, "rem".to_string(), CType::Bool));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(3, "na".to_string(), CType::Bool),
+        Some(signed(hi(0)).lt(int(0))),
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(4, "nb".to_string(), CType::Bool),
+        Some(signed(hi(1)).lt(int(0))),
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(5, "q".to_string(), soft_ty()),
+        None,
+    ));
+
+    let neg_if = |cond: Box<Expression>, local: usize| {
+        stmt(Expression::If {
+            cond,
+            then: Box::new(Expression::unbvari(local).assign(Box::new(call(
+                &format!("{SOFT_PREFIX}neg"),
+                vec![Expression::unbvari(local)],
+            )))),
+        })
+    };
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(neg_if(Expression::vari(3), 0));
+    bb.push(neg_if(Expression::vari(4), 1));
+    bb.push(stmt(Expression::unbvari(5).assign(Box::new(call(
+        &format!("{SOFT_PREFIX}divrem_u"),
+        vec![
+            Expression::unbvari(0),
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+        ],
+    )))));
+    let negative = Expression::BinaryOp {
+        op: BinOpType::Xor,
+        lhs: Expression::vari(3),
+        rhs: Expression::vari(4)
+            & Box::new(Expression::UnaryOp {
+                op: UnaryOpType::Not,
+                val: Expression::vari(2),
+            }),
+    };
+    bb.push(neg_if(Box::new(negative), 5));
+    bb.push(ret(Expression::vari(5)));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
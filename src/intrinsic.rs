@@ -0,0 +1,909 @@
+use rustc_middle::mir::{NonDivergingIntrinsic, Operand};
+use rustc_middle::ty::{Instance, ParamEnv, Ty, TyKind};
+use rustc_span::source_map::Spanned;
+use tracing::{debug, debug_span, warn};
+
+use crate::{
+    atomic,
+    base::Context,
+    bb::{BasicBlock, BasicBlockIdentifier},
+    crepr::Representable,
+    definition::{CVarDecl, CVarDef},
+    expression::{BinOpType, Expression, UnaryOpType, VariableAccess},
+    fatptr::FAT_PTR_META_FIELD,
+    function::{CFunction, CodegenFunctionCx},
+    header::{handle_div_rem, CDefine},
+    int128, simd,
+    stmt::{handle_operand, handle_operand_with_access, handle_shift, handle_transmute, Statement},
+    ty::{CType, CUIntTy},
+    vtable,
+};
+
+// preprocessor conditions for compilers providing the builtins, everything else uses the fallbacks
+const GNUC_CONDITION: &str = "defined(__GNUC__)";
+const CLANG_CONDITION: &str = "defined(__clang__)";
+
+const LIKELY_NAME: &str = "codegenc_likely";
+const UNLIKELY_NAME: &str = "codegenc_unlikely";
+const ASSUME_NAME: &str = "codegenc_assume";
+const VOLATILE_COPY_NAME: &str = "codegenc_volatile_copy";
+const VOLATILE_SET_NAME: &str = "codegenc_volatile_set";
+
+// float intrinsics with a math.h function of the same name
+const MATH_FNS: &[&str] = &[
+    "sqrt",
+    "sin",
+    "cos",
+    "pow",
+    "exp",
+    "exp2",
+    "log",
+    "log10",
+    "log2",
+    "fma",
+    "fabs",
+    "copysign",
+    "fmod",
+    "floor",
+    "ceil",
+    "trunc",
+    "rint",
+    "nearbyint",
+    "round",
+];
+
+fn call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FnCall {
+        function: Expression::constant(&function.to_string()),
+        args,
+    }
+}
+
+fn handle_args<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+) -> Vec<Expression> {
+    args.iter()
+        .map(|arg| handle_operand(fn_cx, &arg.node))
+        .collect()
+}
+
+/// Lowers a call to a compiler intrinsic to the C builtin, libc or math.h function implementing it.
+/// Returns None for intrinsics without a lowering
+pub fn handle_intrinsic<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    instance: Instance<'tcx>,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+    destination: Expression,
+) -> Option<Statement> {
+    let _span = debug_span!("handle_intrinsic").entered();
+
+    let name = fn_cx.tcx.item_name(instance.def_id());
+    let name = name.as_str();
+    debug!("Intrinsic {} with args {:?}", name, instance.args);
+
+    if let Some(op) = name.strip_prefix("atomic_") {
+        return Some(atomic::handle_atomic_intrinsic(
+            fn_cx,
+            instance,
+            op,
+            args,
+            destination,
+        ));
+    }
+
+    if let Some(op) = name.strip_prefix("simd_") {
+        return simd::handle_simd_intrinsic(fn_cx, instance, op, args, destination);
+    }
+
+    let value = match name {
+        "ctpop" | "ctlz" | "ctlz_nonzero" | "cttz" | "cttz_nonzero" | "bswap" | "bitreverse" => {
+            let ty = instance.args.type_at(0);
+            let function = bit_op_define(fn_cx, name.trim_end_matches("_nonzero"), &ty);
+            let value = call(&function, handle_args(fn_cx, args));
+            let ret_ty = return_type(fn_cx, instance);
+            // reordering returns a 128-bit integer, which can't be cast without a native type
+            if int128::is_int128(&ty) && matches!(name, "bswap" | "bitreverse") {
+                let context = &mut fn_cx.ongoing_codegen.context;
+                int128::handle_int_cast(context, value, &fn_cx.tcx.types.u128, &ty, &ret_ty)
+            } else {
+                Expression::Cast {
+                    ty: ret_ty,
+                    value: Box::new(value),
+                }
+            }
+        }
+
+        "rotate_left" | "rotate_right" => {
+            let ty = instance.args.type_at(0);
+            let mut args = handle_args(fn_cx, args);
+            let shift = args.pop().unwrap();
+            let value = args.pop().unwrap();
+            rotate(fn_cx, &ty, value, shift, name == "rotate_left")
+        }
+
+        "copy_nonoverlapping" | "copy" => {
+            let ty = instance.args.type_at(0);
+            let [src, dst, count]: [Expression; 3] = handle_args(fn_cx, args).try_into().unwrap();
+            let function = if name == "copy" { "memmove" } else { "memcpy" };
+            let size = byte_count(fn_cx, &ty, count);
+            return Some(Statement::from_expression(call(
+                function,
+                vec![dst, src, size],
+            )));
+        }
+
+        "write_bytes" => {
+            let ty = instance.args.type_at(0);
+            let [dst, val, count]: [Expression; 3] = handle_args(fn_cx, args).try_into().unwrap();
+            let size = byte_count(fn_cx, &ty, count);
+            return Some(Statement::from_expression(call(
+                "memset",
+                vec![dst, val, size],
+            )));
+        }
+
+        "abort" => return Some(Statement::from_expression(call("abort", vec![]))),
+
+        "likely" | "unlikely" => {
+            let function = expect_define(&mut fn_cx.ongoing_codegen.context, name == "likely");
+            call(&function, handle_args(fn_cx, args))
+        }
+
+        "assume" => {
+            let function = assume_define(&mut fn_cx.ongoing_codegen.context);
+            return Some(Statement::from_expression(call(
+                &function,
+                handle_args(fn_cx, args),
+            )));
+        }
+
+        "transmute" | "transmute_unchecked" => {
+            let ty = return_type(fn_cx, instance);
+            handle_transmute(fn_cx, &args[0].node, ty)
+        }
+
+        "volatile_load" => {
+            let ty = instance.args.type_at(0);
+            let src = handle_args(fn_cx, args).pop().unwrap();
+            volatile_deref(fn_cx, &ty, src)
+        }
+
+        "volatile_store" => {
+            let ty = instance.args.type_at(0);
+            let [dst, val]: [Expression; 2] = handle_args(fn_cx, args).try_into().unwrap();
+            let dst = volatile_deref(fn_cx, &ty, dst);
+            return Some(Statement::from_expression(dst.assign(Box::new(val))));
+        }
+
+        "volatile_copy_memory" | "volatile_copy_nonoverlapping_memory" => {
+            let ty = instance.args.type_at(0);
+            let [dst, src, count]: [Expression; 3] = handle_args(fn_cx, args).try_into().unwrap();
+            let size = byte_count(fn_cx, &ty, count);
+            let src = Expression::Cast {
+                ty: volatile_bytes_ty(),
+                value: Box::new(src),
+            };
+            let c_fn = volatile_copy_fn(fn_cx);
+            return Some(Statement::from_expression(volatile_bytes_call(
+                fn_cx,
+                c_fn,
+                vec![dst, src, size],
+            )));
+        }
+
+        "volatile_set_memory" => {
+            let ty = instance.args.type_at(0);
+            let [dst, val, count]: [Expression; 3] = handle_args(fn_cx, args).try_into().unwrap();
+            let size = byte_count(fn_cx, &ty, count);
+            let c_fn = volatile_set_fn(fn_cx);
+            return Some(Statement::from_expression(volatile_bytes_call(
+                fn_cx,
+                c_fn,
+                vec![dst, val, size],
+            )));
+        }
+
+        // overflowing the unchecked operations is undefined, so wrapping is as good as anything
+        "wrapping_add" | "wrapping_sub" | "wrapping_mul" | "unchecked_add" | "unchecked_sub"
+        | "unchecked_mul" => {
+            let op = match &name[name.len() - 3..] {
+                "add" => BinOpType::Add,
+                "sub" => BinOpType::Sub,
+                _ => BinOpType::Mul,
+            };
+            let rust_ty = instance.args.type_at(0);
+            let ty = fn_cx.rust_to_c_type(&rust_ty);
+            let [lhs, rhs]: [Expression; 2] = handle_args(fn_cx, args).try_into().unwrap();
+            if int128::is_int128(&rust_ty) {
+                let context = &mut fn_cx.ongoing_codegen.context;
+                int128::wrapping(context, op, lhs, rhs, &rust_ty)
+            } else {
+                Expression::wrapping(op, lhs, rhs, &ty)
+            }
+        }
+
+        "unchecked_shl" | "unchecked_shr" => {
+            let op = match name {
+                "unchecked_shl" => BinOpType::Shl,
+                _ => BinOpType::Shr,
+            };
+            let ty = instance.args.type_at(0);
+            let rhs_ty = fn_cx.monomorphize(args[1].node.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
+            let [lhs, rhs]: [Expression; 2] = handle_args(fn_cx, args).try_into().unwrap();
+            handle_shift(fn_cx, op, lhs, rhs, &ty, &rhs_ty)
+        }
+
+        "size_of_val" | "min_align_of_val" => {
+            let ty = instance.args.type_at(0);
+            match size_or_align_of_val(fn_cx, &ty, &args[0].node, name == "size_of_val") {
+                Some(value) => value,
+                None => {
+                    warn!("Unimplemented {} of {:?}", name, ty);
+                    return None;
+                }
+            }
+        }
+
+        "exact_div" | "unchecked_div" | "unchecked_rem" => {
+            let op = match name {
+                "unchecked_rem" => BinOpType::Mod,
+                _ => BinOpType::Div,
+            };
+            let ty = instance.args.type_at(0);
+            let [lhs, rhs]: [Expression; 2] = handle_args(fn_cx, args).try_into().unwrap();
+            if int128::is_int128(&ty) {
+                let context = &mut fn_cx.ongoing_codegen.context;
+                int128::handle_binary_op(context, op, lhs, rhs, &ty, &ty)
+            } else {
+                handle_div_rem(fn_cx, op, lhs, rhs, &ty)
+            }
+        }
+
+        _ => match math_fn_name(name) {
+            Some(function) => call(&function, handle_args(fn_cx, args)),
+            None => {
+                warn!("Unimplemented intrinsic: {}", name);
+                return None;
+            }
+        },
+    };
+
+    Some(Statement::from_expression(
+        destination.assign(Box::new(value)),
+    ))
+}
+
+fn return_type<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    instance: Instance<'tcx>,
+) -> CType {
+    let sig = instance
+        .ty(fn_cx.tcx, ParamEnv::reveal_all())
+        .fn_sig(fn_cx.tcx);
+    let sig = fn_cx
+        .tcx
+        .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), sig);
+    fn_cx.rust_to_c_type(&sig.output())
+}
+
+/// Lowers the intrinsics MIR keeps as statements
+pub fn handle_non_diverging_intrinsic<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    intrinsic: &NonDivergingIntrinsic<'tcx>,
+) -> Expression {
+    match intrinsic {
+        NonDivergingIntrinsic::Assume(op) => {
+            let function = assume_define(&mut fn_cx.ongoing_codegen.context);
+            call(&function, vec![handle_operand(fn_cx, op)])
+        }
+        NonDivergingIntrinsic::CopyNonOverlapping(copy) => {
+            let src_ty = fn_cx.monomorphize(copy.src.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
+            let ty = src_ty
+                .builtin_deref(true)
+                .unwrap_or_else(|| panic!("Copy from a non pointer type: {:?}", src_ty));
+
+            let src = handle_operand(fn_cx, &copy.src);
+            let dst = handle_operand(fn_cx, &copy.dst);
+            let count = handle_operand(fn_cx, &copy.count);
+            let size = byte_count(fn_cx, &ty, count);
+            call("memcpy", vec![dst, src, size])
+        }
+    }
+}
+
+/// Size or alignment of the value behind a pointer, which is read from the metadata for unsized types.
+/// Returns None for structs with unsized tails
+fn size_or_align_of_val<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ty: &Ty<'tcx>,
+    ptr: &Operand<'tcx>,
+    size: bool,
+) -> Option<Expression> {
+    let layout_bytes = |fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>, ty: Ty<'tcx>| {
+        let layout = fn_cx.tcx.layout_of(ParamEnv::reveal_all().and(ty)).unwrap();
+        let bytes = if size {
+            layout.size.bytes()
+        } else {
+            layout.align.abi.bytes()
+        };
+        Expression::const_int(bytes as i128)
+    };
+
+    if ty.is_sized(fn_cx.tcx, ParamEnv::reveal_all()) {
+        return Some(layout_bytes(fn_cx, *ty));
+    }
+
+    match ty.kind() {
+        TyKind::Dynamic(..) => {
+            let mut access = vtable::vtable_access(fn_cx, *ty);
+            access.push(if size {
+                vtable::size_field()
+            } else {
+                vtable::align_field()
+            });
+            Some(handle_operand_with_access(fn_cx, ptr, access))
+        }
+        TyKind::Slice(_) | TyKind::Str => {
+            let elem_ty = ty.sequence_element_type(fn_cx.tcx);
+            if !size {
+                return Some(layout_bytes(fn_cx, elem_ty));
+            }
+
+            let len_ty = fn_cx.rust_to_c_type(&fn_cx.tcx.types.usize);
+            let len = handle_operand_with_access(
+                fn_cx,
+                ptr,
+                vec![
+                    VariableAccess::Field {
+                        name: FAT_PTR_META_FIELD.to_string(),
+                    },
+                    VariableAccess::Cast { ty: len_ty },
+                ],
+            );
+            Some(byte_count(fn_cx, &elem_ty, len))
+        }
+        _ => None,
+    }
+}
+
+/// Size in bytes of `count` values of `ty`
+fn byte_count<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ty: &Ty<'tcx>,
+    count: Expression,
+) -> Expression {
+    let size = fn_cx
+        .tcx
+        .layout_of(ParamEnv::reveal_all().and(*ty))
+        .unwrap()
+        .size
+        .bytes();
+
+    match size {
+        1 => count,
+        size => Expression::BinaryOp {
+            op: BinOpType::Mul,
+            lhs: Box::new(count),
+            rhs: Box::new(Expression::const_int(size as i128)),
+        },
+    }
+}
+
+/// The math.h function of a float intrinsic, e.g. `sqrtf32` -> `sqrtf`
+pub fn math_fn_name(name: &str) -> Option<String> {
+    let (base, suffix) = if let Some(base) = name.strip_suffix("f16") {
+        // there are no _Float16 functions, the float ones are exact enough after rounding back
+        (base, "f")
+    } else if let Some(base) = name.strip_suffix("f32") {
+        (base, "f")
+    } else if let Some(base) = name.strip_suffix("f64") {
+        (base, "")
+    } else if let Some(base) = name.strip_suffix("f128") {
+        (base, "f128")
+    } else {
+        return None;
+    };
+
+    let function = match base {
+        "minnum" => "fmin",
+        "maxnum" => "fmax",
+        // the exponent is converted to a float by C
+        "powi" => "pow",
+        // rint rounds half to even under the default rounding mode
+        "roundeven" => "rint",
+        _ if MATH_FNS.contains(&base) => base,
+        _ => return None,
+    };
+
+    match suffix {
+        // the suffix depends on which type f128 is, see float.rs
+        "f128" => Some(format!("codegenc_f128_fn({function})")),
+        _ => Some(format!("{function}{suffix}")),
+    }
+}
+
+fn unsigned_ty(ty: &CType) -> CUIntTy {
+    match ty {
+        CType::Int(int_ty) => CUIntTy::from(int_ty.bit_width()),
+        CType::UInt(uint_ty) => uint_ty.clone(),
+        _ => panic!("Expected integer type, found {:?}", ty),
+    }
+}
+
+// (T)(((UT)x << (n % w)) | ((UT)x >> ((w - n % w) % w)))
+fn rotate<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ty: &Ty<'tcx>,
+    value: Expression,
+    shift: Expression,
+    left: bool,
+) -> Expression {
+    let c_ty = fn_cx.rust_to_c_type(ty);
+    let unsigned = unsigned_ty(&c_ty);
+    let width = Box::new(Expression::const_int(unsigned.bit_width() as i128));
+
+    let shift = Box::new(Expression::BinaryOp {
+        op: BinOpType::Mod,
+        lhs: Box::new(shift),
+        rhs: width.clone(),
+    });
+    let inverse = Box::new(Expression::BinaryOp {
+        op: BinOpType::Mod,
+        lhs: width.clone() - shift.clone(),
+        rhs: width,
+    });
+
+    // the same through the int128 macros, the shift amounts are u32s
+    if int128::is_int128(ty) {
+        let (u128_ty, u32_ty) = (fn_cx.tcx.types.u128, fn_cx.tcx.types.u32);
+        let context = &mut fn_cx.ongoing_codegen.context;
+        let value = int128::handle_int_cast(context, value, ty, &u128_ty, &CType::UInt(unsigned));
+
+        let (first, second) = if left {
+            (BinOpType::Shl, BinOpType::Shr)
+        } else {
+            (BinOpType::Shr, BinOpType::Shl)
+        };
+        let lhs =
+            int128::handle_binary_op(context, first, value.clone(), *shift, &u128_ty, &u32_ty);
+        let rhs = int128::handle_binary_op(context, second, value, *inverse, &u128_ty, &u32_ty);
+        let rotated =
+            int128::handle_binary_op(context, BinOpType::Or, lhs, rhs, &u128_ty, &u128_ty);
+        return int128::handle_int_cast(context, rotated, &u128_ty, ty, &c_ty);
+    }
+
+    let value = Box::new(Expression::Cast {
+        ty: CType::UInt(unsigned),
+        value: Box::new(value),
+    });
+
+    let rotated = if left {
+        (value.clone() << shift) | (value >> inverse)
+    } else {
+        (value.clone() >> shift) | (value << inverse)
+    };
+
+    Expression::Cast {
+        ty: c_ty,
+        value: rotated,
+    }
+}
+
+/// Defines the macro for a bit counting or reordering intrinsic on `ty`,
+/// returns the name of the macro
+fn bit_op_define<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: &str,
+    ty: &Ty<'tcx>,
+) -> String {
+    let unsigned = unsigned_ty(&fn_cx.rust_to_c_type(ty));
+    let width = unsigned.bit_width();
+    let ut = unsigned.name_str();
+
+    let name = format!("codegenc_{op}_u{width}");
+    let define_name = format!("{name}(x)");
+    let context = &mut fn_cx.ongoing_codegen.context;
+    if context.has_define_with_name(&define_name) {
+        return name;
+    }
+
+    // 128-bit values are split into their halves, which go through the 64-bit macro
+    if width == 128 {
+        let u64_ty = fn_cx.tcx.types.u64;
+        let half = bit_op_define(fn_cx, op, &u64_ty);
+        let context = &mut fn_cx.ongoing_codegen.context;
+        let x = || *Expression::constant(&"x".to_string());
+        let hi = int128::high_half(context, x()).default_repr_str();
+        let lo = int128::low_half(context, x()).default_repr_str();
+
+        let value = match op {
+            "ctpop" => format!("((uint32_t)({half}({lo}) + {half}({hi})))"),
+            "ctlz" => format!("({hi} != 0 ? {half}({hi}) : 64 + {half}({lo}))"),
+            "cttz" => format!("({lo} != 0 ? {half}({lo}) : 64 + {half}({hi}))"),
+            // the reordered halves swap places
+            "bswap" | "bitreverse" => {
+                let half = |value: &String| *Expression::constant(&format!("{half}({value})"));
+                int128::from_halves(context, &CType::UInt(unsigned), half(&lo), half(&hi))
+                    .default_repr_str()
+            }
+            _ => panic!("Unexpected bit operation: {}", op),
+        };
+        context
+            .get_mut_defines()
+            .push(CDefine::new(define_name, value));
+        return name;
+    }
+
+    let x = format!("(uint64_t)({ut})(x)");
+
+    let (condition, builtin) = match (op, width) {
+        ("ctpop", _) => (
+            GNUC_CONDITION,
+            format!("((uint32_t)__builtin_popcountll({x}))"),
+        ),
+        // the builtins are undefined for 0
+        ("ctlz", _) => (
+            GNUC_CONDITION,
+            format!(
+                "(({x}) == 0 ? {width} : (uint32_t)__builtin_clzll({x}) - {})",
+                64 - width
+            ),
+        ),
+        ("cttz", _) => (
+            GNUC_CONDITION,
+            format!("(({x}) == 0 ? {width} : (uint32_t)__builtin_ctzll({x}))"),
+        ),
+        // a single byte has nothing to reorder
+        ("bswap", 8) => {
+            context
+                .get_mut_defines()
+                .push(CDefine::new(define_name, format!("(({ut})(x))")));
+            return name;
+        }
+        ("bswap", _) => (GNUC_CONDITION, format!("__builtin_bswap{width}(({ut})(x))")),
+        ("bitreverse", _) => (
+            CLANG_CONDITION,
+            format!("__builtin_bitreverse{width}(({ut})(x))"),
+        ),
+        _ => panic!("Unexpected bit operation: {}", op),
+    };
+
+    let fallback_fn = bit_op_fallback_fn(op, unsigned);
+    let fallback = format!("{}(({ut})(x))", fallback_fn.get_name());
+    if !context.exists_header_fn_with_name(fallback_fn.get_name()) {
+        context.get_mut_header_functions().push(fallback_fn);
+    }
+
+    context.get_mut_defines().push(CDefine::new_conditional(
+        define_name,
+        condition.to_string(),
+        builtin,
+        fallback,
+    ));
+    name
+}
+
+// Portable implementation of a bit operation, looping over the bits of x
+// ret codegenc_{op}_fallback_u{w}(UT x) {
+//     ret n = init;
+//     uint32_t i = 0;
+// bb0:
+//     if (done) goto bb2;
+// bb1:
+//     body;
+//     goto bb0;
+// bb2:
+//     return n;
+// }
+fn bit_op_fallback_fn(op: &str, unsigned: CUIntTy) -> CFunction {
+    let width = unsigned.bit_width() as i128;
+    let x_ty = CType::UInt(unsigned);
+    let count_ty = CType::UInt(CUIntTy::UInt32);
+
+    let x = || Expression::vari(0);
+    let n = || Expression::vari(1);
+    let i = || Expression::vari(2);
+    let int = |value: i128| Box::new(Expression::const_int(value));
+    let assign = |local: usize, value: Box<Expression>| Expression::unbvari(local).assign(value);
+
+    // x = x >> bits
+    let shift_x = |bits: i128| assign(0, x() >> int(bits));
+
+    let (ret_ty, init, done, body) = match op {
+        "ctpop" => (
+            count_ty,
+            0,
+            x().equ(int(0)),
+            vec![assign(1, n() + (x() & int(1))), shift_x(1)],
+        ),
+        // n ends up as the number of bits above the highest set one
+        "ctlz" => (
+            count_ty,
+            width,
+            x().equ(int(0)),
+            vec![shift_x(1), assign(1, n() - int(1))],
+        ),
+        "cttz" => (
+            count_ty,
+            0,
+            (x() & int(1)).equ(int(1)) | n().equ(int(width)),
+            vec![shift_x(1), assign(1, n() + int(1))],
+        ),
+        "bswap" => (
+            x_ty.clone(),
+            0,
+            i().equ(int(width / 8)),
+            vec![
+                assign(1, (n() << int(8)) | (x() & int(0xff))),
+                shift_x(8),
+                assign(2, i() + int(1)),
+            ],
+        ),
+        "bitreverse" => (
+            x_ty.clone(),
+            0,
+            i().equ(int(width)),
+            vec![
+                assign(1, (n() << int(1)) | (x() & int(1))),
+                shift_x(1),
+                assign(2, i() + int(1)),
+            ],
+        ),
+        _ => panic!("Unexpected bit operation: {}", op),
+    };
+
+    let mut c_fn = CFunction::new(format!("codegenc_{op}_fallback_u{width}"), ret_ty.clone());
+    c_fn.add_signature_var(CVarDef::new(0, "x".to_string(), x_ty));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(1, "n".to_string(), ret_ty),
+        Some(int(init)),
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(2, "i".to_string(), CType::UInt(CUIntTy::UInt32)),
+        Some(int(0)),
+    ));
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.push(Statement::from_expression(Expression::SwitchJump {
+        value: done,
+        cases: vec![(int(0), BasicBlockIdentifier(1))],
+        default: BasicBlockIdentifier(2),
+    }));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    for expression in body {
+        bb1.push(Statement::from_expression(expression));
+    }
+    bb1.push(Statement::from_expression(Expression::Goto {
+        target: BasicBlockIdentifier(0),
+    }));
+
+    let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
+    bb2.push(Statement::from_expression(Expression::Return {
+        value: n(),
+    }));
+
+    c_fn.push_bb(bb0);
+    c_fn.push_bb(bb1);
+    c_fn.push_bb(bb2);
+    c_fn
+}
+
+// #define codegenc_likely(b) __builtin_expect(!!(b), 1)
+fn expect_define(context: &mut Context, likely: bool) -> String {
+    let name = if likely { LIKELY_NAME } else { UNLIKELY_NAME };
+    let define_name = format!("{name}(b)");
+
+    if !context.has_define_with_name(&define_name) {
+        context.get_mut_defines().push(CDefine::new_conditional(
+            define_name,
+            GNUC_CONDITION.to_string(),
+            format!("__builtin_expect(!!(b), {})", likely as u8),
+            "(b)".to_string(),
+        ));
+    }
+    name.to_string()
+}
+
+// #define codegenc_assume(b) ((b) ? (void)0 : __builtin_unreachable())
+fn assume_define(context: &mut Context) -> String {
+    let define_name = format!("{ASSUME_NAME}(b)");
+
+    if !context.has_define_with_name(&define_name) {
+        context.get_mut_defines().push(CDefine::new_conditional(
+            define_name,
+            GNUC_CONDITION.to_string(),
+            "((b) ? (void)0 : __builtin_unreachable())".to_string(),
+            "((void)(b))".to_string(),
+        ));
+    }
+    ASSUME_NAME.to_string()
+}
+
+// *((volatile T*) ptr), or *((T* volatile*) ptr) for pointer pointees
+fn volatile_deref<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ty: &Ty<'tcx>,
+    ptr: Expression,
+) -> Expression {
+    let pointee = fn_cx.rust_to_c_type(ty);
+
+    Expression::UnaryOp {
+        op: UnaryOpType::Deref,
+        val: Box::new(Expression::Cast {
+            ty: CType::Pointer(Box::new(CType::Volatile(Box::new(pointee)))),
+            value: Box::new(ptr),
+        }),
+    }
+}
+
+fn volatile_bytes_ty() -> CType {
+    CType::Pointer(Box::new(CType::Volatile(Box::new(CType::UInt(
+        CUIntTy::UInt8,
+    )))))
+}
+
+/// Calls a byte-wise volatile memory function, adding it to the header if needed.
+/// The destination is passed as `volatile uint8_t*`
+fn volatile_bytes_call<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    c_fn: CFunction,
+    args: Vec<Expression>,
+) -> Expression {
+    let name = c_fn.get_name().to_string();
+    let context = &mut fn_cx.ongoing_codegen.context;
+    if !context.exists_header_fn_with_name(&name) {
+        context.get_mut_header_functions().push(c_fn);
+    }
+
+    let args = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, arg)| match i {
+            0 => Expression::Cast {
+                ty: volatile_bytes_ty(),
+                value: Box::new(arg),
+            },
+            _ => arg,
+        })
+        .collect();
+    call(&name, args)
+}
+
+fn byte_index(ptr: usize, index: usize) -> Expression {
+    Expression::Variable {
+        local: ptr,
+        access: vec![VariableAccess::Index {
+            expression: Expression::unbvari(index),
+        }],
+    }
+}
+
+// copies byte by byte in the direction that is safe for overlapping memory
+// void codegenc_volatile_copy(volatile uint8_t* dst, volatile uint8_t* src, usize n) {
+//     usize i = 0;
+// bb0: if (dst < src) goto bb1; else goto bb3;
+// bb1: if (i == n) goto bb5;
+// bb2: dst[i] = src[i]; i = i + 1; goto bb1;
+// bb3: if (n == 0) goto bb5;
+// bb4: n = n - 1; dst[n] = src[n]; goto bb3;
+// bb5:
+// }
+fn volatile_copy_fn<'tcx, 'ccx>(fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>) -> CFunction {
+    let size_ty = fn_cx.rust_to_c_type(&fn_cx.tcx.types.usize);
+
+    let mut c_fn = CFunction::new(VOLATILE_COPY_NAME.to_string(), CType::Void);
+    c_fn.add_signature_var(CVarDef::new(0, "dst".to_string(), volatile_bytes_ty()));
+    c_fn.add_signature_var(CVarDef::new(1, "src".to_string(), volatile_bytes_ty()));
+    c_fn.add_signature_var(CVarDef::new(2, "n".to_string(), size_ty.clone()));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(3, "i".to_string(), size_ty),
+        Some(Box::new(Expression::const_int(0))),
+    ));
+
+    let branch = |cond: Box<Expression>, then: usize, otherwise: usize| {
+        Statement::from_expression(Expression::SwitchJump {
+            value: cond,
+            cases: vec![(
+                Box::new(Expression::const_int(0)),
+                BasicBlockIdentifier(otherwise),
+            )],
+            default: BasicBlockIdentifier(then),
+        })
+    };
+    let goto = |target: usize| {
+        Statement::from_expression(Expression::Goto {
+            target: BasicBlockIdentifier(target),
+        })
+    };
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.push(branch(Expression::vari(0).lt(Expression::vari(1)), 1, 3));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(branch(Expression::vari(3).equ(Expression::vari(2)), 5, 2));
+
+    let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
+    bb2.push(Statement::from_expression(
+        byte_index(0, 3).assign(Box::new(byte_index(1, 3))),
+    ));
+    bb2.push(Statement::from_expression(Expression::unbvari(3).assign(
+        Expression::vari(3) + Box::new(Expression::const_int(1)),
+    )));
+    bb2.push(goto(1));
+
+    let mut bb3 = BasicBlock::new(BasicBlockIdentifier(3));
+    bb3.push(branch(
+        Expression::vari(2).equ(Box::new(Expression::const_int(0))),
+        5,
+        4,
+    ));
+
+    let mut bb4 = BasicBlock::new(BasicBlockIdentifier(4));
+    bb4.push(Statement::from_expression(Expression::unbvari(2).assign(
+        Expression::vari(2) - Box::new(Expression::const_int(1)),
+    )));
+    bb4.push(Statement::from_expression(
+        byte_index(0, 2).assign(Box::new(byte_index(1, 2))),
+    ));
+    bb4.push(goto(3));
+
+    let bb5 = BasicBlock::new(BasicBlockIdentifier(5));
+
+    c_fn.push_bb(bb0);
+    c_fn.push_bb(bb1);
+    c_fn.push_bb(bb2);
+    c_fn.push_bb(bb3);
+    c_fn.push_bb(bb4);
+    c_fn.push_bb(bb5);
+    c_fn
+}
+
+// void codegenc_volatile_set(volatile uint8_t* dst, uint8_t val, usize n) {
+//     usize i = 0;
+// bb0: if (i == n) goto bb2;
+// bb1: dst[i] = val; i = i + 1; goto bb0;
+// bb2:
+// }
+fn volatile_set_fn<'tcx, 'ccx>(fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>) -> CFunction {
+    let size_ty = fn_cx.rust_to_c_type(&fn_cx.tcx.types.usize);
+
+    let mut c_fn = CFunction::new(VOLATILE_SET_NAME.to_string(), CType::Void);
+    c_fn.add_signature_var(CVarDef::new(0, "dst".to_string(), volatile_bytes_ty()));
+    c_fn.add_signature_var(CVarDef::new(
+        1,
+        "val".to_string(),
+        CType::UInt(CUIntTy::UInt8),
+    ));
+    c_fn.add_signature_var(CVarDef::new(2, "n".to_string(), size_ty.clone()));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(3, "i".to_string(), size_ty),
+        Some(Box::new(Expression::const_int(0))),
+    ));
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.push(Statement::from_expression(Expression::SwitchJump {
+        value: Expression::vari(3).equ(Expression::vari(2)),
+        cases: vec![(Box::new(Expression::const_int(0)), BasicBlockIdentifier(1))],
+        default: BasicBlockIdentifier(2),
+    }));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(Statement::from_expression(
+        byte_index(0, 3).assign(Expression::vari(1)),
+    ));
+    bb1.push(Statement::from_expression(Expression::unbvari(3).assign(
+        Expression::vari(3) + Box::new(Expression::const_int(1)),
+    )));
+    bb1.push(Statement::from_expression(Expression::Goto {
+        target: BasicBlockIdentifier(0),
+    }));
+
+    let bb2 = BasicBlock::new(BasicBlockIdentifier(2));
+
+    c_fn.push_bb(bb0);
+    c_fn.push_bb(bb1);
+    c_fn.push_bb(bb2);
+    c_fn
+}
@@ -26,20 +26,30 @@ use tracing::debug;
 
 mod aggregate;
 mod alloc;
+mod asm;
+mod atomic;
 mod base;
 mod bb;
 mod crepr;
 mod definition;
 mod expression;
 mod fatptr;
+mod float;
 mod function;
 mod header;
 mod include;
+mod int128;
+mod intrinsic;
+mod panic;
 mod prefix;
+mod simd;
 mod stmt;
 mod structure;
 mod ty;
+mod unsize;
+mod unwind;
 mod utils;
+mod vtable;
 mod write;
 
 pub struct CCodegenBackend(());
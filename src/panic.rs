@@ -0,0 +1,518 @@
+use std::env;
+
+use rustc_const_eval::interpret::{alloc_range, Scalar};
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::interpret::GlobalAlloc;
+use rustc_middle::mir::{
+    AggregateKind, AssertKind, Body, ConstOperand, ConstValue, Local, Operand, Rvalue,
+    StatementKind, TerminatorKind,
+};
+use rustc_middle::ty::{ParamEnv, TyCtxt, TyKind};
+use rustc_span::source_map::Spanned;
+use rustc_span::Span;
+use rustc_target::abi::Size;
+
+use crate::{
+    base::Context,
+    bb::{BasicBlock, BasicBlockIdentifier},
+    definition::{CVarDecl, CVarDef},
+    expression::Expression,
+    function::{CFunction, CodegenFunctionCx},
+    stmt::{handle_operand, Statement},
+    ty::{CType, CUIntTy},
+    unwind,
+    utils::escape_c_string,
+};
+
+pub const PANIC_FN_NAME: &str = "codegenc_panic";
+pub const DEFAULT_PANIC_HANDLER_NAME: &str = "rust_c_panic";
+pub const PANIC_BOUNDS_CHECK_FN_NAME: &str = "codegenc_panic_bounds_check";
+pub const PANIC_MISALIGNED_FN_NAME: &str = "codegenc_panic_misaligned_pointer";
+
+// size of the buffer messages with runtime values are formatted into
+const MSG_BUF_SIZE: usize = 128;
+
+fn c_str_type() -> CType {
+    CType::Pointer(Box::new(CType::Opaque("const char".to_string())))
+}
+
+fn string_literal(s: &str) -> Expression {
+    Expression::Constant {
+        value: format!("\"{}\"", escape_c_string(s)),
+    }
+}
+
+fn call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FnCall {
+        function: Box::new(Expression::Constant {
+            value: function.to_string(),
+        }),
+        args,
+    }
+}
+
+/// The user-provided panic handler named by C_CODEGEN_PANIC_HANDLER, if any
+fn user_panic_handler() -> Option<String> {
+    match env::var("C_CODEGEN_PANIC_HANDLER") {
+        Ok(val) if !val.is_empty() => Some(val),
+        _ => None,
+    }
+}
+
+fn panic_handler_name() -> String {
+    user_panic_handler().unwrap_or(DEFAULT_PANIC_HANDLER_NAME.to_string())
+}
+
+/// Adds the location parameters (file, line, col) shared by all panic functions, starting at `first_id`
+fn add_location_params(c_fn: &mut CFunction, first_id: usize) {
+    c_fn.add_signature_var(CVarDef::new(first_id, "file".to_string(), c_str_type()));
+    c_fn.add_signature_var(CVarDef::new(
+        first_id + 1,
+        "line".to_string(),
+        CType::UInt(CUIntTy::UInt32),
+    ));
+    c_fn.add_signature_var(CVarDef::new(
+        first_id + 2,
+        "col".to_string(),
+        CType::UInt(CUIntTy::UInt32),
+    ));
+}
+
+/// Builds the panic functions, if they weren't built yet
+pub fn build_panic_runtime(context: &mut Context) {
+    if context.exists_header_fn_with_name(PANIC_FN_NAME) {
+        return;
+    }
+
+    if unwind::is_enabled() {
+        unwind::build_unwind_runtime(context);
+    }
+
+    context.get_mut_header_functions().extend(vec![
+        panic_handler_fn(),
+        panic_fn(),
+        formatted_panic_fn(
+            PANIC_BOUNDS_CHECK_FN_NAME,
+            ("len", "index"),
+            "index out of bounds: the len is %llu but the index is %llu",
+        ),
+        formatted_panic_fn(
+            PANIC_MISALIGNED_FN_NAME,
+            ("required", "found"),
+            "misaligned pointer dereference: address must be a multiple of 0x%llx but is 0x%llx",
+        ),
+    ]);
+}
+
+// void rust_c_panic(const char* msg, const char* file, uint32_t line);
+// or, if the user provides no handler
+// __attribute__((weak)) void rust_c_panic(const char* msg, const char* file, uint32_t line) {
+//     fprintf(stderr, "panicked at %s:%u:\n%s\n", file, line, msg);
+//     abort(); (only if unwinding is disabled, codegenc_panic unwinds after the handler returns)
+// }
+fn panic_handler_fn() -> CFunction {
+    let mut c_fn = CFunction::new(panic_handler_name(), CType::Void);
+    c_fn.add_signature_var(CVarDef::new(0, "msg".to_string(), c_str_type()));
+    c_fn.add_signature_var(CVarDef::new(1, "file".to_string(), c_str_type()));
+    c_fn.add_signature_var(CVarDef::new(
+        2,
+        "line".to_string(),
+        CType::UInt(CUIntTy::UInt32),
+    ));
+
+    if user_panic_handler().is_some() {
+        c_fn.set_extern();
+        return c_fn;
+    }
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(Statement::from_expression(call(
+        "fprintf",
+        vec![
+            Expression::Constant {
+                value: "stderr".to_string(),
+            },
+            string_literal("panicked at %s:%u:\n%s\n"),
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+            Expression::unbvari(0),
+        ],
+    )));
+    if !unwind::is_enabled() {
+        bb.push(Statement::from_expression(call("abort", vec![])));
+    }
+
+    c_fn.set_weak();
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+// void codegenc_panic(const char* msg, const char* file, uint32_t line, uint32_t col) {
+//     rust_c_panic(msg, file, line);
+//     abort(); or codegenc_resume_unwind();
+// }
+fn panic_fn() -> CFunction {
+    let mut c_fn = CFunction::new(PANIC_FN_NAME.to_string(), CType::Void);
+    c_fn.add_signature_var(CVarDef::new(0, "msg".to_string(), c_str_type()));
+    add_location_params(&mut c_fn, 1);
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(Statement::from_expression(call(
+        &panic_handler_name(),
+        vec![
+            Expression::unbvari(0),
+            Expression::unbvari(1),
+            Expression::unbvari(2),
+        ],
+    )));
+    // handlers that return continue with the panic strategy
+    bb.push(Statement::from_expression(unwind::resume_unwind()));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+// void name(uint64_t a, uint64_t b, const char* file, uint32_t line, uint32_t col) {
+//     char msg[128];
+//     snprintf(msg, 128, fmt, (unsigned long long)a, (unsigned long long)b);
+//     codegenc_panic(msg, file, line, col);
+// }
+fn formatted_panic_fn(name: &str, values: (&str, &str), fmt: &str) -> CFunction {
+    let value_ty = CType::UInt(CUIntTy::UInt64);
+    let printf_ty = CType::Opaque("unsigned long long".to_string());
+
+    let mut c_fn = CFunction::new(name.to_string(), CType::Void);
+    c_fn.add_signature_var(CVarDef::new(0, values.0.to_string(), value_ty.clone()));
+    c_fn.add_signature_var(CVarDef::new(1, values.1.to_string(), value_ty));
+    add_location_params(&mut c_fn, 2);
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(
+            5,
+            "msg".to_string(),
+            CType::Array(Box::new(CType::Opaque("char".to_string())), MSG_BUF_SIZE),
+        ),
+        None,
+    ));
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(Statement::from_expression(call(
+        "snprintf",
+        vec![
+            Expression::unbvari(5),
+            Expression::const_int(MSG_BUF_SIZE as i128),
+            string_literal(fmt),
+            Expression::Cast {
+                ty: printf_ty.clone(),
+                value: Expression::vari(0),
+            },
+            Expression::Cast {
+                ty: printf_ty,
+                value: Expression::vari(1),
+            },
+        ],
+    )));
+    bb.push(Statement::from_expression(call(
+        PANIC_FN_NAME,
+        vec![
+            Expression::unbvari(5),
+            Expression::unbvari(2),
+            Expression::unbvari(3),
+            Expression::unbvari(4),
+        ],
+    )));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+/// The file, line and column arguments of panic functions, as rustc would report them for `span`
+pub fn location_args<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    span: Span,
+) -> Vec<Expression> {
+    let source_map = fn_cx.tcx.sess.source_map();
+    let loc = source_map.lookup_char_pos(span.lo());
+    let file = source_map.span_to_filename(span);
+
+    vec![
+        string_literal(&file.prefer_local().to_string()),
+        Expression::const_int(loc.line as i128),
+        Expression::const_int(loc.col.to_usize() as i128 + 1),
+    ]
+}
+
+/// Calls the panic function reporting a failed assert, with the message rustc would produce
+pub fn handle_assert_failure<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    msg: &AssertKind<Operand<'tcx>>,
+    span: Span,
+) -> Expression {
+    build_panic_runtime(&mut fn_cx.ongoing_codegen.context);
+
+    let location = location_args(fn_cx, span);
+
+    let (function, mut args) = match msg {
+        AssertKind::BoundsCheck { len, index } => (
+            PANIC_BOUNDS_CHECK_FN_NAME,
+            vec![handle_operand(fn_cx, len), handle_operand(fn_cx, index)],
+        ),
+        AssertKind::MisalignedPointerDereference { required, found } => (
+            PANIC_MISALIGNED_FN_NAME,
+            vec![
+                handle_operand(fn_cx, required),
+                handle_operand(fn_cx, found),
+            ],
+        ),
+        _ => (PANIC_FN_NAME, vec![string_literal(msg.description())]),
+    };
+
+    args.extend(location);
+    call(function, args)
+}
+
+// the message of panics whose message can't be known at compile time
+const RUNTIME_MSG: &str = "panicked with a message formatted at runtime";
+
+// core::panicking, the module of the panic_fmt lang item
+fn panicking_module(tcx: TyCtxt<'_>) -> Option<DefId> {
+    tcx.opt_parent(tcx.lang_items().panic_fmt()?)
+}
+
+/// Functions of the standard library that start a panic, those of core::panicking and std's begin_panic
+pub fn is_panic_fn(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    if tcx.lang_items().begin_panic_fn() == Some(def_id) {
+        return true;
+    }
+
+    let Some(panicking) = panicking_module(tcx) else {
+        return false;
+    };
+    // the panic_const_* lang items live in a submodule
+    std::iter::successors(tcx.opt_parent(def_id), |&parent| tcx.opt_parent(parent))
+        .any(|parent| parent == panicking)
+}
+
+/// Calls the panic function in place of an explicit panic.
+/// Messages formatted at runtime aren't evaluated, only their literal pieces are reported
+pub fn handle_explicit_panic<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+    span: Span,
+) -> Expression {
+    let msg = panic_message(fn_cx, args);
+
+    build_panic_runtime(&mut fn_cx.ongoing_codegen.context);
+
+    let mut args = vec![string_literal(&msg)];
+    args.extend(location_args(fn_cx, span));
+    call(PANIC_FN_NAME, args)
+}
+
+fn panic_message<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+) -> String {
+    // panic!() of the 2021 edition
+    let Some(arg) = args.first() else {
+        return "explicit panic".to_string();
+    };
+    let ty = fn_cx.monomorphize(arg.node.ty(fn_cx.mir, fn_cx.tcx));
+
+    let msg = match ty.ty_adt_def() {
+        // panic!("msg", ..) of the 2021 edition
+        Some(adt) if fn_cx.tcx.lang_items().format_arguments() == Some(adt.did()) => {
+            format_pieces(fn_cx, &arg.node)
+        }
+        // assert_eq! and assert_ne!, rustc's message without the values
+        Some(adt)
+            if adt.is_enum() && fn_cx.tcx.opt_parent(adt.did()) == panicking_module(fn_cx.tcx) =>
+        {
+            assert_kind(fn_cx, &arg.node).map(|op| format!("assertion `left {op} right` failed"))
+        }
+        _ => const_str(fn_cx, &arg.node),
+    };
+
+    msg.unwrap_or_else(|| RUNTIME_MSG.to_string())
+}
+
+fn const_str<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    operand: &Operand<'tcx>,
+) -> Option<String> {
+    let Operand::Constant(constant) = operand else {
+        return None;
+    };
+    if !fn_cx.monomorphize(constant.ty()).peel_refs().is_str() {
+        return None;
+    }
+
+    let value = constant
+        .const_
+        .eval(fn_cx.tcx, ParamEnv::reveal_all(), constant.span)
+        .ok()?;
+    let bytes = match value {
+        ConstValue::Slice { .. } => value.try_get_slice_bytes_for_diagnostics(fn_cx.tcx)?,
+        _ => return None,
+    };
+
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+// the comparison of the core::panicking::AssertKind passed to assert_failed
+fn assert_kind<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    operand: &Operand<'tcx>,
+) -> Option<&'static str> {
+    let ty = fn_cx.monomorphize(operand.ty(fn_cx.mir, fn_cx.tcx));
+    let adt = ty.ty_adt_def()?;
+
+    let variant = match operand {
+        Operand::Constant(constant) => {
+            let value = constant
+                .const_
+                .eval(fn_cx.tcx, ParamEnv::reveal_all(), constant.span)
+                .ok()?;
+            let ConstValue::Scalar(Scalar::Int(discr)) = value else {
+                return None;
+            };
+            let discr = discr.to_uint(discr.size());
+            adt.discriminants(fn_cx.tcx)
+                .find(|(_, d)| d.val == discr)
+                .map(|(idx, _)| idx)?
+        }
+        Operand::Copy(place) | Operand::Move(place) => {
+            match local_definition(fn_cx.mir, place.as_local()?)? {
+                Definition::Rvalue(Rvalue::Aggregate(kind, _)) => match **kind {
+                    AggregateKind::Adt(_, variant, ..) => variant,
+                    _ => return None,
+                },
+                Definition::Rvalue(Rvalue::Use(operand)) => {
+                    return assert_kind(fn_cx, operand);
+                }
+                _ => return None,
+            }
+        }
+    };
+
+    match adt.variant(variant).name.as_str() {
+        "Eq" => Some("=="),
+        "Ne" => Some("!="),
+        _ => Some("matches"),
+    }
+}
+
+// the literal pieces of the fmt::Arguments `operand`, the values formatted between them are left out
+fn format_pieces<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    operand: &Operand<'tcx>,
+) -> Option<String> {
+    // the pieces are the first argument of the Arguments constructors, and the first field of Arguments
+    let pieces = match local_definition(fn_cx.mir, operand.place()?.as_local()?)? {
+        Definition::Call(args) => &args.first()?.node,
+        Definition::Rvalue(Rvalue::Aggregate(_, fields)) => fields.iter().next()?,
+        _ => return None,
+    };
+
+    let constant = const_operand(fn_cx.mir, pieces)?;
+    let value = constant
+        .const_
+        .eval(fn_cx.tcx, ParamEnv::reveal_all(), constant.span)
+        .ok()?;
+
+    // the pieces are a &[&str; N], or the slice it is coerced to
+    let (alloc, offset, len) = match value {
+        ConstValue::Scalar(Scalar::Ptr(ptr, _)) => {
+            let pointee = fn_cx.monomorphize(constant.ty()).builtin_deref(true)?;
+            let TyKind::Array(_, len) = pointee.kind() else {
+                return None;
+            };
+            let (prov, offset) = ptr.into_parts();
+            let GlobalAlloc::Memory(alloc) = fn_cx.tcx.global_alloc(prov.alloc_id()) else {
+                return None;
+            };
+            (
+                alloc,
+                offset,
+                len.try_eval_target_usize(fn_cx.tcx, ParamEnv::reveal_all())?,
+            )
+        }
+        ConstValue::Slice { data, meta } => (data, Size::ZERO, meta),
+        _ => return None,
+    };
+
+    // &str is a pointer to the bytes followed by the length
+    let ptr_size = fn_cx.tcx.data_layout.pointer_size;
+    let mut msg = String::new();
+    for idx in 0..len {
+        let piece = offset + ptr_size * 2 * idx;
+        let Scalar::Ptr(str_ptr, _) = alloc
+            .inner()
+            .read_scalar(&fn_cx.tcx, alloc_range(piece, ptr_size), true)
+            .ok()?
+        else {
+            return None;
+        };
+        let str_len = alloc
+            .inner()
+            .read_scalar(&fn_cx.tcx, alloc_range(piece + ptr_size, ptr_size), false)
+            .ok()?
+            .to_target_usize(&fn_cx.tcx)
+            .ok()?;
+
+        let (prov, str_offset) = str_ptr.into_parts();
+        let GlobalAlloc::Memory(str_alloc) = fn_cx.tcx.global_alloc(prov.alloc_id()) else {
+            return None;
+        };
+        let start = str_offset.bytes_usize();
+        let bytes = str_alloc
+            .inner()
+            .inspect_with_uninit_and_ptr_outside_interpreter(start..start + str_len as usize);
+        msg.push_str(&String::from_utf8_lossy(bytes));
+    }
+
+    Some(msg)
+}
+
+enum Definition<'a, 'tcx> {
+    Rvalue(&'a Rvalue<'tcx>),
+    // the arguments of the call returning the local
+    Call(&'a [Spanned<Operand<'tcx>>]),
+}
+
+// the assignment of a temporary, which is assigned exactly once
+fn local_definition<'a, 'tcx>(mir: &'a Body<'tcx>, local: Local) -> Option<Definition<'a, 'tcx>> {
+    mir.basic_blocks.iter().find_map(|block_data| {
+        let assigned = block_data.statements.iter().find_map(|statement| {
+            let StatementKind::Assign(assign) = &statement.kind else {
+                return None;
+            };
+            let (place, rvalue) = &**assign;
+            (place.as_local() == Some(local)).then_some(Definition::Rvalue(rvalue))
+        });
+
+        assigned.or_else(|| match &block_data.terminator().kind {
+            TerminatorKind::Call {
+                args, destination, ..
+            } if destination.as_local() == Some(local) => Some(Definition::Call(args)),
+            _ => None,
+        })
+    })
+}
+
+// follows moves and casts of locals back to the constant they were assigned
+fn const_operand<'a, 'tcx>(
+    mir: &'a Body<'tcx>,
+    operand: &'a Operand<'tcx>,
+) -> Option<&'a ConstOperand<'tcx>> {
+    match operand {
+        Operand::Constant(constant) => Some(constant),
+        Operand::Copy(place) | Operand::Move(place) => {
+            match local_definition(mir, place.as_local()?)? {
+                Definition::Rvalue(Rvalue::Use(operand) | Rvalue::Cast(_, operand, _)) => {
+                    const_operand(mir, operand)
+                }
+                _ => None,
+            }
+        }
+    }
+}
@@ -2,17 +2,24 @@ use crate::base::Context;
 use crate::bb::{BasicBlock, BasicBlockIdentifier};
 use crate::definition::CVarDef;
 use crate::fatptr::new_fat_ptr;
+use crate::float;
 use crate::function::CFunction;
+use crate::header::CDefine;
 
 use crate::include::Include;
 use crate::structure::CComposite;
 use crate::ty::CIntTy;
 use crate::ty::CType;
+use crate::unwind;
 
 use crate::expression::Expression;
 use crate::stmt::Statement;
 
 pub fn build_prefix(context: &mut Context) {
+    // Feature test macros
+    context
+        .get_mut_feature_macros()
+        .append(&mut prefix_feature_macros());
     // Header Includes
     context
         .get_mut_h_includes()
@@ -21,6 +28,21 @@ pub fn build_prefix(context: &mut Context) {
     context.get_mut_functions().append(&mut prefix_functions());
     // Structs
     context.get_mut_structs().append(&mut prefix_structs());
+    // Unwinding runtime
+    if unwind::is_enabled() {
+        unwind::build_unwind_runtime(context);
+    }
+}
+
+// Macros asking the system headers for declarations they leave out by default
+fn prefix_feature_macros() -> Vec<CDefine> {
+    vec![
+        // the _Float128 math functions of math.h, e.g. sqrtf128
+        CDefine::new(
+            "__STDC_WANT_IEC_60559_TYPES_EXT__".to_string(),
+            "1".to_string(),
+        ),
+    ]
 }
 
 // Greedy list of includes
@@ -44,7 +66,14 @@ fn prefix_header_includes() -> Vec<Include> {
         Include::new("stdarg.h".to_string(), true),
         Include::new("locale.h".to_string(), true),
         Include::new("uchar.h".to_string(), true),
+        Include::new("stdatomic.h".to_string(), true),
         Include::new("complex.h".to_string(), true),
+        // after float.h, the condition depends on LDBL_MANT_DIG
+        Include::new_conditional(
+            "quadmath.h".to_string(),
+            true,
+            float::QUADMATH_CONDITION.to_string(),
+        ),
     ]
 }
 
@@ -0,0 +1,585 @@
+use rustc_middle::mir::{ConstValue, Operand, Place};
+use rustc_middle::ty::{Instance, ParamEnv, Ty};
+use rustc_span::source_map::Spanned;
+use rustc_target::abi::Endian;
+use tracing::{debug, debug_span};
+
+use crate::{
+    base::Context,
+    bb::{BasicBlock, BasicBlockIdentifier},
+    crepr::Representable,
+    definition::{CVarDecl, CVarDef},
+    expression::{BinOpType, Expression, UnaryOpType, VariableAccess},
+    function::{CFunction, CodegenFunctionCx},
+    header::CDefine,
+    stmt::{handle_operand, handle_operand_with_access, handle_place, Statement},
+    ty::{CCompositeInfo, CType, CUIntTy},
+};
+
+// compilers with the GCC vector extensions, everything else stores the lanes in an array
+const VECTOR_EXT_CONDITION: &str = "defined(__GNUC__)";
+
+fn call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FnCall {
+        function: Expression::constant(&function.to_string()),
+        args,
+    }
+}
+
+/// Name of the macro declaring a vector of `lanes` elements
+pub fn vector_type_name(elem: &CType, lanes: usize) -> String {
+    let elem_name: String = format!("{:?}", elem)
+        .chars()
+        .map(|c| match c {
+            ' ' | '*' => '_',
+            _ => c,
+        })
+        .collect();
+
+    format!("codegenc_vec_{elem_name}_{lanes}")
+}
+
+/// The vector extension only supports power of two vectors of ints and floats
+fn has_vector_ext(elem: &CType, lanes: usize) -> bool {
+    lanes.is_power_of_two() && matches!(elem, CType::Int(_) | CType::UInt(_) | CType::Float(_))
+}
+
+fn elem_size(elem: &CType) -> u64 {
+    match elem {
+        CType::Int(int_ty) => int_ty.bit_width() / 8,
+        CType::UInt(uint_ty) => uint_ty.bit_width() / 8,
+        CType::Float(float_ty) => float_ty.bit_width() / 8,
+        _ => panic!("Vector extension of non numeric type: {:?}", elem),
+    }
+}
+
+// #if defined(__GNUC__)
+// #define codegenc_vec_T_N(name) T name __attribute__((vector_size(N * sizeof(T))))
+// #else
+// #define codegenc_vec_T_N(name) T name[N]
+// #endif
+pub fn add_vector_define(context: &mut Context, elem: &CType, lanes: usize) {
+    let name = format!("{}(name)", vector_type_name(elem, lanes));
+    if context.has_define_with_name(&name) {
+        return;
+    }
+
+    let array = format!("{:?} name[{lanes}]", elem);
+    let define = if has_vector_ext(elem, lanes) {
+        CDefine::new_conditional(
+            name,
+            VECTOR_EXT_CONDITION.to_string(),
+            format!(
+                "{:?} name __attribute__((vector_size({})))",
+                elem,
+                elem_size(elem) * lanes as u64
+            ),
+            array,
+        )
+    } else {
+        CDefine::new(name, array)
+    };
+
+    context.get_mut_defines().push(define);
+}
+
+/// Struct info, element type and lane count of a simd type
+fn simd_info<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ty: &Ty<'tcx>,
+) -> (CCompositeInfo, CType, usize) {
+    let info = fn_cx.rust_to_c_type(ty).as_composite_info();
+    let (lanes, elem_ty) = ty.simd_size_and_type(fn_cx.tcx);
+    (info, fn_cx.rust_to_c_type(&elem_ty), lanes as usize)
+}
+
+// v.wrapee[index]
+fn lane(local: usize, index: Expression) -> Expression {
+    Expression::Variable {
+        local,
+        access: vec![
+            VariableAccess::Unwrap,
+            VariableAccess::Index { expression: index },
+        ],
+    }
+}
+
+fn operand_lane<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    operand: &Operand<'tcx>,
+    index: Expression,
+) -> Expression {
+    handle_operand_with_access(
+        fn_cx,
+        operand,
+        vec![
+            VariableAccess::Unwrap,
+            VariableAccess::Index { expression: index },
+        ],
+    )
+}
+
+fn add_header_fn(context: &mut Context, c_fn: CFunction) {
+    if !context.exists_header_fn_with_name(c_fn.get_name()) {
+        context.get_mut_header_functions().push(c_fn);
+    }
+}
+
+/// Lowers the `simd_*` platform intrinsics, `op` is the name without the `simd_` prefix
+pub fn handle_simd_intrinsic<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    instance: Instance<'tcx>,
+    op: &str,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+    destination: Expression,
+) -> Option<Statement> {
+    let _span = debug_span!("handle_simd_intrinsic").entered();
+    debug!("Simd intrinsic {} with args {:?}", op, instance.args);
+
+    let ty = instance.args.type_at(0);
+
+    let value = match op {
+        "add" | "sub" | "mul" | "div" | "rem" | "and" | "or" | "xor" | "shl" | "shr" => {
+            let bin_op = match op {
+                "add" => BinOpType::Add,
+                "sub" => BinOpType::Sub,
+                "mul" => BinOpType::Mul,
+                "div" => BinOpType::Div,
+                "rem" => BinOpType::Mod,
+                "and" => BinOpType::And,
+                "or" => BinOpType::Or,
+                "xor" => BinOpType::Xor,
+                "shl" => BinOpType::Shl,
+                _ => BinOpType::Shr,
+            };
+            let function = lanewise_define(fn_cx, op, bin_op, &ty, &ty);
+            call(&function, handle_args(fn_cx, args))
+        }
+
+        // lanes of the result are all ones if the comparison holds, zero otherwise
+        "eq" | "ne" | "lt" | "le" | "gt" | "ge" => {
+            let bin_op = match op {
+                "eq" => BinOpType::Eq,
+                "ne" => BinOpType::Ne,
+                "lt" => BinOpType::Lt,
+                "le" => BinOpType::Le,
+                "gt" => BinOpType::Gt,
+                _ => BinOpType::Ge,
+            };
+            let mask_ty = instance.args.type_at(1);
+            let function = lanewise_define(fn_cx, op, bin_op, &ty, &mask_ty);
+            call(&function, handle_args(fn_cx, args))
+        }
+
+        "extract" => {
+            let index = handle_operand(fn_cx, &args[1].node);
+            operand_lane(fn_cx, &args[0].node, index)
+        }
+
+        "insert" => {
+            let c_fn = insert_fn(fn_cx, &ty);
+            let function = c_fn.get_name().to_string();
+            add_header_fn(&mut fn_cx.ongoing_codegen.context, c_fn);
+            call(&function, handle_args(fn_cx, args))
+        }
+
+        "shuffle" => {
+            let out_ty = instance.args.type_at(2);
+            let (out_info, _, _) = simd_info(fn_cx, &out_ty);
+            let (_, _, in_lanes) = simd_info(fn_cx, &ty);
+
+            let lanes = shuffle_indices(fn_cx, &args[2].node)
+                .into_iter()
+                .map(|idx| {
+                    let (vector, idx) = if idx < in_lanes as u64 {
+                        (&args[0].node, idx)
+                    } else {
+                        (&args[1].node, idx - in_lanes as u64)
+                    };
+                    operand_lane(fn_cx, vector, Expression::const_int(idx as i128))
+                })
+                .collect();
+
+            Expression::Struct {
+                name: Expression::constant(&out_info.name),
+                fields: vec![Expression::InitializerList { fields: lanes }],
+            }
+        }
+
+        _ => match op.strip_prefix("reduce_") {
+            Some(reduction) => reduce(fn_cx, reduction, &ty, args)?,
+            None => return None,
+        },
+    };
+
+    Some(Statement::from_expression(
+        destination.assign(Box::new(value)),
+    ))
+}
+
+fn handle_args<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+) -> Vec<Expression> {
+    args.iter()
+        .map(|arg| handle_operand(fn_cx, &arg.node))
+        .collect()
+}
+
+/// Defines the macro applying `op` to each pair of lanes of two vectors of `ty`, producing a vector of `out_ty`.
+/// With the vector extension this is a single vector operation, otherwise a loop over the lanes
+fn lanewise_define<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: &str,
+    bin_op: BinOpType,
+    ty: &Ty<'tcx>,
+    out_ty: &Ty<'tcx>,
+) -> String {
+    let (info, elem, lanes) = simd_info(fn_cx, ty);
+    let (out_info, out_elem, _) = simd_info(fn_cx, out_ty);
+    let is_mask = out_ty != ty;
+
+    let name = format!("codegenc_simd_{op}_{}", out_info.name);
+    let define_name = format!("{name}(a, b)");
+    let context = &mut fn_cx.ongoing_codegen.context;
+    if context.has_define_with_name(&define_name) {
+        return name;
+    }
+
+    let float_rem = matches!(bin_op, BinOpType::Mod) && matches!(elem, CType::Float(_));
+    let fallback_fn = lanewise_fallback_fn(&name, &bin_op, &info, &out_info, &elem, lanes, is_mask);
+    let fallback = format!("{}(a, b)", fallback_fn.get_name());
+    add_header_fn(context, fallback_fn);
+
+    if !has_vector_ext(&elem, lanes) || !has_vector_ext(&out_elem, lanes) || float_rem {
+        context
+            .get_mut_defines()
+            .push(CDefine::new(define_name, fallback));
+        return name;
+    }
+
+    let op_str = bin_op.default_repr_str();
+    let vector_op = if is_mask {
+        // comparisons produce signed vectors, which are reinterpreted as the mask type
+        format!(
+            "({}())((a).wrapee {op_str} (b).wrapee)",
+            vector_type_name(&out_elem, lanes)
+        )
+    } else {
+        format!("(a).wrapee {op_str} (b).wrapee")
+    };
+
+    context.get_mut_defines().push(CDefine::new_conditional(
+        define_name,
+        VECTOR_EXT_CONDITION.to_string(),
+        format!("(({}){{ {vector_op} }})", out_info.name),
+        fallback,
+    ));
+    name
+}
+
+// Out codegenc_simd_{op}_fallback_{Out}(In a, In b) {
+//     Out r;
+//     uint32_t i = 0;
+// bb0:
+//     if (i == lanes) goto bb2;
+// bb1:
+//     r.wrapee[i] = a.wrapee[i] op b.wrapee[i]; (or -(a.wrapee[i] op b.wrapee[i]) for masks)
+//     i = i + 1;
+//     goto bb0;
+// bb2:
+//     return r;
+// }
+fn lanewise_fallback_fn(
+    name: &str,
+    bin_op: &BinOpType,
+    info: &CCompositeInfo,
+    out_info: &CCompositeInfo,
+    elem: &CType,
+    lanes: usize,
+    is_mask: bool,
+) -> CFunction {
+    let in_ty = CType::Struct(info.clone());
+    let out_ty = CType::Struct(out_info.clone());
+
+    let mut c_fn = CFunction::new(
+        name.replacen("codegenc_simd_", "codegenc_simd_fallback_", 1),
+        out_ty.clone(),
+    );
+    c_fn.add_signature_var(CVarDef::new(0, "a".to_string(), in_ty.clone()));
+    c_fn.add_signature_var(CVarDef::new(1, "b".to_string(), in_ty));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(2, "r".to_string(), out_ty),
+        None,
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(3, "i".to_string(), CType::UInt(CUIntTy::UInt32)),
+        Some(Box::new(Expression::const_int(0))),
+    ));
+
+    let a = lane(0, Expression::unbvari(3));
+    let b = lane(1, Expression::unbvari(3));
+    let value = match (bin_op, elem) {
+        (BinOpType::Mod, CType::Float(float_ty)) if float_ty.bit_width() == 32 => {
+            call("fmodf", vec![a, b])
+        }
+        (BinOpType::Mod, CType::Float(_)) => call("fmod", vec![a, b]),
+        _ => Expression::BinaryOp {
+            op: bin_op.clone(),
+            lhs: Box::new(a),
+            rhs: Box::new(b),
+        },
+    };
+    let value = if is_mask {
+        Expression::UnaryOp {
+            op: UnaryOpType::Neg,
+            val: Box::new(value),
+        }
+    } else {
+        value
+    };
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.push(Statement::from_expression(Expression::SwitchJump {
+        value: Expression::vari(3).equ(Box::new(Expression::const_int(lanes as i128))),
+        cases: vec![(Box::new(Expression::const_int(0)), BasicBlockIdentifier(1))],
+        default: BasicBlockIdentifier(2),
+    }));
+
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(Statement::from_expression(
+        lane(2, Expression::unbvari(3)).assign(Box::new(value)),
+    ));
+    bb1.push(Statement::from_expression(Expression::unbvari(3).assign(
+        Expression::vari(3) + Box::new(Expression::const_int(1)),
+    )));
+    bb1.push(Statement::from_expression(Expression::Goto {
+        target: BasicBlockIdentifier(0),
+    }));
+
+    let mut bb2 = BasicBlock::new(BasicBlockIdentifier(2));
+    bb2.push(Statement::from_expression(Expression::Return {
+        value: Expression::vari(2),
+    }));
+
+    c_fn.push_bb(bb0);
+    c_fn.push_bb(bb1);
+    c_fn.push_bb(bb2);
+    c_fn
+}
+
+// V codegenc_simd_insert_V(V v, uint32_t idx, T val) {
+//     v.wrapee[idx] = val;
+//     return v;
+// }
+fn insert_fn<'tcx, 'ccx>(fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>, ty: &Ty<'tcx>) -> CFunction {
+    let (info, elem, _) = simd_info(fn_cx, ty);
+    let vector_ty = CType::Struct(info.clone());
+
+    let mut c_fn = CFunction::new(
+        format!("codegenc_simd_insert_{}", info.name),
+        vector_ty.clone(),
+    );
+    c_fn.add_signature_var(CVarDef::new(0, "v".to_string(), vector_ty));
+    c_fn.add_signature_var(CVarDef::new(
+        1,
+        "idx".to_string(),
+        CType::UInt(CUIntTy::UInt32),
+    ));
+    c_fn.add_signature_var(CVarDef::new(2, "val".to_string(), elem));
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    bb.push(Statement::from_expression(
+        lane(0, Expression::unbvari(1)).assign(Expression::vari(2)),
+    ));
+    bb.push(Statement::from_expression(Expression::Return {
+        value: Expression::vari(0),
+    }));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+/// Reads the constant lane indices of a shuffle
+fn shuffle_indices<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    indices: &Operand<'tcx>,
+) -> Vec<u64> {
+    let Operand::Constant(constant) = indices else {
+        panic!("Expected constant shuffle indices, got {:?}", indices);
+    };
+
+    let ty = fn_cx.monomorphize(constant.ty());
+    let size = fn_cx
+        .tcx
+        .layout_of(ParamEnv::reveal_all().and(ty))
+        .unwrap()
+        .size
+        .bytes_usize();
+
+    let value = fn_cx
+        .monomorphize(constant.const_)
+        .eval(fn_cx.tcx, ParamEnv::reveal_all(), constant.span)
+        .expect("Shuffle indices evaluation failed");
+    let ConstValue::Indirect { alloc_id, offset } = value else {
+        panic!("Expected shuffle indices in memory, got {:?}", value);
+    };
+
+    let alloc = fn_cx.tcx.global_alloc(alloc_id).unwrap_memory();
+    let start = offset.bytes_usize();
+    let bytes: Vec<u8> = alloc
+        .inner()
+        .inspect_with_uninit_and_ptr_outside_interpreter(start..start + size)
+        .into();
+
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            let chunk: [u8; 4] = chunk.try_into().unwrap();
+            match fn_cx.tcx.data_layout.endian {
+                Endian::Little => u32::from_le_bytes(chunk) as u64,
+                Endian::Big => u32::from_be_bytes(chunk) as u64,
+            }
+        })
+        .collect()
+}
+
+/// Folds the lanes of a vector, returns None for unknown reductions
+fn reduce<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    reduction: &str,
+    ty: &Ty<'tcx>,
+    args: &Vec<Spanned<Operand<'tcx>>>,
+) -> Option<Expression> {
+    let (_, _, lanes) = simd_info(fn_cx, ty);
+    let vector = &args[0].node;
+
+    let (bin_op, acc) = match reduction {
+        // the ordered reductions start from the accumulator argument
+        "add_ordered" => (BinOpType::Add, Some(handle_operand(fn_cx, &args[1].node))),
+        "mul_ordered" => (BinOpType::Mul, Some(handle_operand(fn_cx, &args[1].node))),
+        "add_unordered" => (BinOpType::Add, None),
+        "mul_unordered" => (BinOpType::Mul, None),
+        "and" | "all" => (BinOpType::And, None),
+        "or" | "any" => (BinOpType::Or, None),
+        "xor" => (BinOpType::Xor, None),
+        "min" | "max" => {
+            let c_fn = reduce_extremum_fn(fn_cx, reduction == "max", ty);
+            let function = c_fn.get_name().to_string();
+            add_header_fn(&mut fn_cx.ongoing_codegen.context, c_fn);
+            return Some(call(&function, vec![handle_operand(fn_cx, vector)]));
+        }
+        _ => return None,
+    };
+
+    let is_bool = matches!(reduction, "all" | "any");
+    let mut lanes = (0..lanes).map(|idx| {
+        let lane = operand_lane(fn_cx, vector, Expression::const_int(idx as i128));
+        if is_bool {
+            *lane.neq(Box::new(Expression::const_int(0)))
+        } else {
+            lane
+        }
+    });
+
+    let first = match acc {
+        Some(acc) => acc,
+        None => lanes.next().unwrap(),
+    };
+    Some(lanes.fold(first, |lhs, rhs| Expression::BinaryOp {
+        op: bin_op.clone(),
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }))
+}
+
+// T codegenc_simd_reduce_max_V(V v) {
+//     T r = v.wrapee[0];
+//     if (v.wrapee[1] > r) r = v.wrapee[1];
+//     ...
+//     return r;
+// }
+fn reduce_extremum_fn<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    max: bool,
+    ty: &Ty<'tcx>,
+) -> CFunction {
+    let (info, elem, lanes) = simd_info(fn_cx, ty);
+    let op = if max { "max" } else { "min" };
+
+    let mut c_fn = CFunction::new(
+        format!("codegenc_simd_reduce_{op}_{}", info.name),
+        elem.clone(),
+    );
+    c_fn.add_signature_var(CVarDef::new(0, "v".to_string(), CType::Struct(info)));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(1, "r".to_string(), elem),
+        Some(Box::new(lane(0, Expression::const_int(0)))),
+    ));
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    for idx in 1..lanes {
+        let value = lane(0, Expression::const_int(idx as i128));
+        let replace = if max {
+            value.gt(Expression::vari(1))
+        } else {
+            value.lt(Expression::vari(1))
+        };
+        bb.push(Statement::from_expression(Expression::If {
+            cond: replace,
+            then: Box::new(Expression::unbvari(1).assign(Box::new(value))),
+        }));
+    }
+    bb.push(Statement::from_expression(Expression::Return {
+        value: Expression::vari(1),
+    }));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+/// Builds a simd struct from its fields. Tuple-like simd structs list the lanes,
+/// simd structs with an array field are written through the array view of the vector
+pub fn handle_simd_aggregate<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    place: &Place<'tcx>,
+    ty: &Ty<'tcx>,
+    fields: Vec<&Operand<'tcx>>,
+) -> Expression {
+    let (info, _, _) = simd_info(fn_cx, ty);
+
+    if let [field] = fields[..] {
+        let field_ty = fn_cx.monomorphize(field.ty(fn_cx.mir, fn_cx.tcx));
+        if field_ty.is_array() {
+            let array_ty = fn_cx.rust_to_c_type(&field_ty);
+            let Expression::Variable { local, mut access } = handle_place(fn_cx, place) else {
+                panic!("Expected place to be a variable");
+            };
+            access.extend(array_view(array_ty));
+
+            return Expression::Variable { local, access }
+                .assign(Box::new(handle_operand(fn_cx, field)));
+        }
+    }
+
+    let lanes = fields
+        .into_iter()
+        .map(|field| handle_operand(fn_cx, field))
+        .collect();
+    handle_place(fn_cx, place).assign(Box::new(Expression::Struct {
+        name: Expression::constant(&info.name),
+        fields: vec![Expression::InitializerList { fields: lanes }],
+    }))
+}
+
+/// Views the vector of a simd struct as its array field, (*(Array*)&v.wrapee)
+pub fn array_view(array_ty: CType) -> Vec<VariableAccess> {
+    vec![
+        VariableAccess::Unwrap,
+        VariableAccess::Reference,
+        VariableAccess::Cast {
+            ty: CType::Pointer(Box::new(array_ty)),
+        },
+        VariableAccess::Dereference,
+    ]
+}
@@ -1,18 +1,29 @@
 use crate::aggregate::handle_aggregate;
+use crate::alloc;
+use crate::bb::{BasicBlock, BasicBlockIdentifier};
 use crate::crepr::{indent, Representable, RepresentationContext};
-use crate::expression::{Expression, VariableAccess};
-use crate::fatptr::FAT_PTR_META_FIELD;
-use crate::function::{CFunction, CodegenFunctionCx};
-use crate::header::handle_checked_op;
+use crate::definition::{CVarDecl, CVarDef};
+use crate::expression::{BinOpType, Expression, VariableAccess};
+use crate::fatptr::{FAT_PTR_DATA_FIELD, FAT_PTR_META_FIELD};
+use crate::function::{format_fn_name, CFunction, CodegenFunctionCx};
+use crate::header::{handle_checked_op, handle_div_rem, handle_float_to_int, CDefine};
+use crate::int128;
+use crate::intrinsic::{handle_non_diverging_intrinsic, math_fn_name};
+use crate::simd;
 use crate::structure::CTaggedUnionDef;
-use crate::ty::CType;
+use crate::ty::{CIntTy, CType};
+use crate::unsize;
 use crate::utils;
+use crate::vtable;
 use rustc_const_eval::interpret::Provenance;
+use rustc_middle::mir::interpret::{alloc_range, ConstAllocation, GlobalAlloc, Scalar};
 use rustc_middle::mir::{
-    BinOp, CastKind, ConstOperand, ConstValue, Operand, Place, Rvalue, StatementKind,
+    BinOp, CastKind, ConstOperand, ConstValue, Operand, Place, ProjectionElem, Rvalue,
+    StatementKind,
 };
 use rustc_middle::ty::adjustment::PointerCoercion;
-use rustc_middle::ty::{ParamEnv, Ty, TyKind};
+use rustc_middle::ty::{ClosureKind, Instance, ParamEnv, Ty, TyKind};
+use rustc_target::abi::Size;
 use std::fmt::{self, Debug};
 use tracing::{debug, debug_span, error, span, warn};
 
@@ -102,6 +113,8 @@ pub fn handle_stmt<'tcx, 'ccx>(
             }
         }
 
+        StatementKind::Intrinsic(intrinsic) => handle_non_diverging_intrinsic(fn_cx, intrinsic),
+
         _ => Expression::NoOp {},
     };
 
@@ -122,11 +135,20 @@ pub fn handle_place<'tcx, 'ccx>(
 
     let mut access = Vec::new();
 
-    let current_ty = fn_cx.ty_for_local(place.local);
+    let mut ctype = fn_cx.rust_to_c_type(&fn_cx.ty_for_local(place.local));
 
-    let mut ctype = fn_cx.rust_to_c_type(&current_ty);
+    for (proj_idx, proj) in place.projection.iter().enumerate() {
+        // type of the place before this projection is applied
+        let current_ty = fn_cx.monomorphize(
+            Place::ty_from(
+                place.local,
+                &place.projection[..proj_idx],
+                fn_cx.mir,
+                fn_cx.tcx,
+            )
+            .ty,
+        );
 
-    for proj in place.projection {
         match proj {
             rustc_middle::mir::ProjectionElem::Field(field, ty) => {
                 debug!(
@@ -135,6 +157,20 @@ pub fn handle_place<'tcx, 'ccx>(
                 );
 
                 match ctype {
+                    // the fields of simd structs are the lanes of their vector, or an array of all of them
+                    CType::Struct(_) if current_ty.is_simd() => {
+                        ctype = fn_cx.rust_to_c_type(&ty);
+                        if ty.is_array() {
+                            access.extend(simd::array_view(ctype.clone()));
+                        } else {
+                            access.extend(vec![
+                                VariableAccess::Unwrap,
+                                VariableAccess::Index {
+                                    expression: Expression::const_int(field.as_usize() as i128),
+                                },
+                            ]);
+                        }
+                    }
                     CType::Struct(info) | CType::Union(info) => {
                         access.push(VariableAccess::Field {
                             name: fn_cx
@@ -170,8 +206,41 @@ pub fn handle_place<'tcx, 'ccx>(
                 });
             }
 
-            rustc_middle::mir::ProjectionElem::Subslice { .. } => {
-                todo!("Subslice")
+            rustc_middle::mir::ProjectionElem::Subslice { from, to, from_end } => {
+                // subslices of slices are unsized, so they only appear behind a reference and are handled in Rvalue::Ref
+                if !from_end || !matches!(current_ty.kind(), TyKind::Slice(_)) {
+                    assert!(!from_end, "Subslice from the end of an array: {:?}", place);
+
+                    let sub_ty = fn_cx.monomorphize(
+                        Place::ty_from(
+                            place.local,
+                            &place.projection[..=proj_idx],
+                            fn_cx.mir,
+                            fn_cx.tcx,
+                        )
+                        .ty,
+                    );
+                    let sub_ctype = fn_cx.rust_to_c_type(&sub_ty);
+
+                    debug!("Subslice {}..{} of array, as {:?}", from, to, sub_ctype);
+
+                    // view the elements starting at `from` as the smaller array wrapper
+                    access.extend(vec![
+                        VariableAccess::Unwrap,
+                        VariableAccess::Index {
+                            expression: Expression::const_int(from as i128),
+                        },
+                        VariableAccess::Reference,
+                        VariableAccess::Cast {
+                            ty: CType::Pointer(Box::new(sub_ctype.clone())),
+                        },
+                        VariableAccess::Dereference,
+                    ]);
+
+                    ctype = sub_ctype;
+                } else {
+                    panic!("Subslice of a slice not behind a reference: {:?}", place);
+                }
             }
 
             rustc_middle::mir::ProjectionElem::Downcast(_, variant_idx) => {
@@ -212,12 +281,24 @@ pub fn handle_place<'tcx, 'ccx>(
             }
 
             rustc_middle::mir::ProjectionElem::Deref => {
-                let next_ctype = fn_cx.rust_to_c_type(&current_ty.builtin_deref(true).unwrap());
+                let pointee_ty = current_ty.builtin_deref(true).unwrap();
+                let next_ctype = fn_cx.rust_to_c_type(&pointee_ty);
 
                 match ctype {
-                    CType::FatPointer => access.push(VariableAccess::FatPtrDereference {
-                        ty: next_ctype.clone(),
-                    }),
+                    CType::FatPointer => {
+                        // slices are accessed through a pointer to their first element
+                        let data_ctype = match pointee_ty.kind() {
+                            TyKind::Slice(elem_ty) => {
+                                CType::Pointer(Box::new(fn_cx.rust_to_c_type(elem_ty)))
+                            }
+                            TyKind::Str => {
+                                CType::Pointer(Box::new(CType::UInt(crate::ty::CUIntTy::UInt8)))
+                            }
+                            _ => next_ctype.clone(),
+                        };
+
+                        access.push(VariableAccess::FatPtrDereference { ty: data_ctype })
+                    }
 
                     _ => {
                         access.push(VariableAccess::Dereference);
@@ -297,81 +378,511 @@ fn handle_cast<'tcx, 'ccx>(
     let tgt_ty = fn_cx.rust_to_c_type(target_ty);
 
     match kind {
-        CastKind::IntToInt
-        | CastKind::FloatToInt
-        | CastKind::FloatToFloat
-        | CastKind::IntToFloat => {
+        CastKind::IntToInt if int128::is_int128(&source_ty) || int128::is_int128(target_ty) => {
+            let value = handle_operand(fn_cx, op);
+            int128::handle_int_cast(
+                &mut fn_cx.ongoing_codegen.context,
+                value,
+                &source_ty,
+                target_ty,
+                &tgt_ty,
+            )
+        }
+        CastKind::FloatToInt => {
+            let value = handle_operand(fn_cx, op);
+            handle_float_to_int(fn_cx, value, &source_ty, target_ty)
+        }
+        CastKind::IntToInt | CastKind::FloatToFloat | CastKind::IntToFloat => {
             handle_operand_with_access(fn_cx, op, vec![VariableAccess::Cast { ty: tgt_ty }])
         }
         CastKind::PointerCoercion(coercion_type) => {
-            let target_kind = target_ty.builtin_deref(true).unwrap().kind();
-            let source_deref_ty = source_ty.builtin_deref(true).unwrap();
-            let _source_ty = fn_cx.rust_to_c_type(&source_deref_ty);
             debug!("PointerCoercion: {:?}", coercion_type);
-            debug!("source type: {:?}", source_deref_ty);
 
             match coercion_type {
                 PointerCoercion::Unsize => {
-                    debug!("Unsize");
-
-                    if let TyKind::Dynamic(data, _, _dyn_kind) = target_kind {
-                        let alloc_id = fn_cx
-                            .tcx
-                            .vtable_allocation((source_deref_ty.clone(), data.principal()));
-                        let vtable = fn_cx.handle_global_decl(alloc_id);
-
-                        return Expression::fatptr(handle_operand(fn_cx, op), vtable);
-                    }
+                    let source_ty = fn_cx.monomorphize(source_ty);
+                    let target_ty = fn_cx.monomorphize(*target_ty);
+                    let value = handle_operand(fn_cx, op);
+                    unsize::coerce_unsized(fn_cx, value, source_ty, target_ty)
+                }
 
-                    if let TyKind::Array(_, len) = source_deref_ty.kind() {
-                        debug!("Array unsize");
-                        return Expression::fatptr(
-                            handle_operand(fn_cx, op),
-                            Expression::Constant {
-                                value: len
-                                    .try_eval_target_usize(fn_cx.tcx, ParamEnv::reveal_all())
-                                    .expect("Expected array size to be known")
-                                    .to_string(),
-                            },
-                        );
+                PointerCoercion::ReifyFnPointer => {
+                    let source_ty = fn_cx.monomorphize(source_ty);
+                    let TyKind::FnDef(def_id, args) = source_ty.kind() else {
+                        panic!("Expected fn item, found {:?}", source_ty);
+                    };
+                    let instance = Instance::resolve_for_fn_ptr(
+                        fn_cx.tcx,
+                        ParamEnv::reveal_all(),
+                        *def_id,
+                        args,
+                    )
+                    .expect("Instance not found for fn pointer");
+
+                    Expression::Cast {
+                        ty: tgt_ty,
+                        value: Box::new(Expression::Constant {
+                            value: format_fn_name(&fn_cx.tcx.symbol_name(instance)),
+                        }),
                     }
+                }
 
-                    error!(
-                        "Unhandled unsize operation for target kind: {:?} from {:?}",
-                        target_kind, source_ty
-                    );
-                    return Expression::NoOp {};
+                PointerCoercion::ClosureFnPointer(_) => {
+                    let source_ty = fn_cx.monomorphize(source_ty);
+                    closure_fn_pointer(fn_cx, &source_ty, tgt_ty)
                 }
 
+                // the pointee is the same in C, or the first element of the array wrapper
+                PointerCoercion::UnsafeFnPointer
+                | PointerCoercion::MutToConstPointer
+                | PointerCoercion::ArrayToPointer => Expression::Cast {
+                    ty: tgt_ty,
+                    value: Box::new(handle_operand(fn_cx, op)),
+                },
+
                 _ => {
                     panic!("Unhandled pointer coercion type: {:?}", coercion_type);
                 }
             }
         }
 
-        CastKind::Transmute => {
-            if let CType::FatPointer = tgt_ty {
-                return handle_operand(fn_cx, op);
+        CastKind::PtrToPtr | CastKind::FnPtrToPtr => {
+            let source_c_ty = fn_cx.rust_to_c_type(&fn_cx.monomorphize(source_ty));
+            match (source_c_ty, &tgt_ty) {
+                // the metadata is kept, e.g. *const [u8] as *const [i8]
+                (CType::FatPointer, CType::FatPointer) => handle_operand(fn_cx, op),
+                (CType::FatPointer, _) => handle_operand_with_access(
+                    fn_cx,
+                    op,
+                    vec![
+                        VariableAccess::Field {
+                            name: FAT_PTR_DATA_FIELD.to_string(),
+                        },
+                        VariableAccess::Cast { ty: tgt_ty },
+                    ],
+                ),
+                _ => Expression::Cast {
+                    ty: tgt_ty,
+                    value: Box::new(handle_operand(fn_cx, op)),
+                },
             }
+        }
 
-            return handle_operand_with_access(
-                fn_cx,
-                op,
-                vec![
-                    VariableAccess::Reference,
-                    VariableAccess::Cast {
-                        ty: CType::Pointer(Box::new(tgt_ty)),
-                    },
-                ],
-            );
+        // pointers and integers are converted through uintptr_t, the only integer guaranteed to hold a pointer
+        CastKind::PointerExposeProvenance | CastKind::PointerWithExposedProvenance => {
+            let source_c_ty = fn_cx.rust_to_c_type(&fn_cx.monomorphize(source_ty));
+            let value = match source_c_ty {
+                CType::FatPointer => handle_operand_with_access(
+                    fn_cx,
+                    op,
+                    vec![VariableAccess::Field {
+                        name: FAT_PTR_DATA_FIELD.to_string(),
+                    }],
+                ),
+                _ => handle_operand(fn_cx, op),
+            };
+
+            Expression::Cast {
+                ty: tgt_ty,
+                value: Box::new(Expression::Cast {
+                    ty: uintptr_ty(),
+                    value: Box::new(value),
+                }),
+            }
+        }
+
+        // dyn* holds a pointer sized value in place of the data pointer
+        CastKind::DynStar => {
+            let source_ty = fn_cx.monomorphize(source_ty);
+            let target_ty = fn_cx.monomorphize(*target_ty);
+            let vtable = vtable::vtable_ptr(fn_cx, source_ty, target_ty);
+
+            let value = Expression::Cast {
+                ty: CType::Pointer(Box::new(CType::Void)),
+                value: Box::new(Expression::Cast {
+                    ty: uintptr_ty(),
+                    value: Box::new(handle_operand(fn_cx, op)),
+                }),
+            };
+            Expression::fatptr(value, vtable)
         }
 
+        CastKind::Transmute => handle_transmute(fn_cx, op, tgt_ty),
+
         _ => {
             panic!("Unhandled cast kind: {:?}", kind);
         }
     }
 }
 
+fn uintptr_ty() -> CType {
+    CType::Opaque("uintptr_t".to_string())
+}
+
+/// A non capturing closure as a function pointer.
+/// The closure body takes its (empty) environment as the first argument,
+/// so a shim with the signature of the pointer calls it with an environment of its own
+fn closure_fn_pointer<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    closure_ty: &Ty<'tcx>,
+    fn_ptr_ty: CType,
+) -> Expression {
+    let TyKind::Closure(def_id, args) = closure_ty.kind() else {
+        panic!("Expected closure, found {:?}", closure_ty);
+    };
+    let instance = Instance::new(*def_id, args);
+    let closure_name = format_fn_name(&fn_cx.tcx.symbol_name(instance));
+    let shim_name = format!("closure_fn_{closure_name}");
+
+    if !fn_cx
+        .ongoing_codegen
+        .context
+        .exists_header_fn_with_name(shim_name.as_str())
+    {
+        let CType::FunctionPtr(fn_ptr_info) = fn_ptr_ty else {
+            panic!("Expected function pointer type, found {:?}", fn_ptr_ty);
+        };
+        let env_ty = fn_cx.rust_to_c_type(closure_ty);
+        let env_idx = fn_ptr_info.args.len();
+
+        let mut c_fn = CFunction::new(shim_name.clone(), *fn_ptr_info.ret);
+        for (idx, arg) in fn_ptr_info.args.into_iter().enumerate() {
+            c_fn.add_signature_var(CVarDef::new(idx, format!("var{idx}"), arg));
+        }
+        c_fn.add_var_decl(CVarDecl::new(
+            CVarDef::new(env_idx, "env".to_string(), env_ty),
+            None,
+        ));
+
+        // Fn and FnMut closures take their environment by reference
+        let env_access = match args.as_closure().kind() {
+            ClosureKind::FnOnce => vec![],
+            ClosureKind::Fn | ClosureKind::FnMut => vec![VariableAccess::Reference],
+        };
+        let mut call_args = vec![Expression::Variable {
+            local: env_idx,
+            access: env_access,
+        }];
+        call_args.extend((0..env_idx).map(Expression::unbvari));
+
+        let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+        bb.push(Statement::from_expression(Expression::Return {
+            value: Box::new(Expression::FnCall {
+                function: Expression::constant(&closure_name),
+                args: call_args,
+            }),
+        }));
+        c_fn.push_bb(bb);
+
+        fn_cx
+            .ongoing_codegen
+            .context
+            .get_mut_header_functions()
+            .push(c_fn);
+    }
+
+    Expression::Constant { value: shim_name }
+}
+
+/// Reinterprets the bytes of the operand as the target type, `*(T*)&x`
+pub fn handle_transmute<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: &Operand<'tcx>,
+    tgt_ty: CType,
+) -> Expression {
+    if let CType::FatPointer = tgt_ty {
+        return handle_operand(fn_cx, op);
+    }
+
+    let reinterpret = vec![
+        VariableAccess::Reference,
+        VariableAccess::Cast {
+            ty: CType::Pointer(Box::new(tgt_ty.clone())),
+        },
+        VariableAccess::Dereference,
+    ];
+    match handle_operand(fn_cx, op) {
+        Expression::Variable { local, mut access } => {
+            access.extend(reinterpret);
+            Expression::Variable { local, access }
+        }
+        // constants have no address, a compound literal gives them one, `*(T*)&(S){ c }`
+        value => {
+            let src_ty = op.ty(&fn_cx.mir.local_decls, fn_cx.tcx);
+            let literal = Expression::Struct {
+                name: Expression::constant(&fn_cx.rust_to_c_type(&src_ty).default_repr_str()),
+                fields: vec![value],
+            };
+            Expression::Constant {
+                value: format!(
+                    "(*(({})&{}))",
+                    CType::Pointer(Box::new(tgt_ty)).default_repr_str(),
+                    literal.default_repr_str()
+                ),
+            }
+        }
+    }
+}
+
+/// Lowers a three-way comparison to `core::cmp::Ordering`, whose discriminants are -1, 0 and 1
+fn handle_cmp<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    lhs: Expression,
+    rhs: Expression,
+    ty: &Ty<'tcx>,
+    ordering_ty: &Ty<'tcx>,
+) -> Expression {
+    let CType::TaggedUnion(ordering_info) = fn_cx.rust_to_c_type(ordering_ty) else {
+        panic!(
+            "Expected Ordering to be a tagged union, got {:?}",
+            ordering_ty
+        );
+    };
+
+    // (a > b) - (a < b) compares with the operand types, so signedness is kept
+    let (gt, lt) = if int128::is_int128(ty) {
+        let context = &mut fn_cx.ongoing_codegen.context;
+        (
+            int128::handle_binary_op(context, BinOpType::Gt, lhs.clone(), rhs.clone(), ty, ty),
+            int128::handle_binary_op(context, BinOpType::Lt, lhs, rhs, ty, ty),
+        )
+    } else {
+        (
+            Expression::BinaryOp {
+                op: BinOpType::Gt,
+                lhs: Box::new(lhs.clone()),
+                rhs: Box::new(rhs.clone()),
+            },
+            Expression::BinaryOp {
+                op: BinOpType::Lt,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+        )
+    };
+    let tag = Expression::BinaryOp {
+        op: BinOpType::Sub,
+        lhs: Box::new(gt),
+        rhs: Box::new(lt),
+    };
+
+    Expression::Struct {
+        name: Box::new(Expression::Constant {
+            value: ordering_info.name.clone(),
+        }),
+        fields: vec![tag],
+    }
+}
+
+/// Shifts with Rust's semantics, which are defined for every value and amount:
+/// the amount is masked to the bit width, left shifts are done on the unsigned counterpart,
+/// and right shifts of signed values are arithmetic
+pub fn handle_shift<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    op: BinOpType,
+    lhs: Expression,
+    rhs: Expression,
+    ty: &Ty<'tcx>,
+    rhs_ty: &Ty<'tcx>,
+) -> Expression {
+    let c_ty = fn_cx.rust_to_c_type(ty);
+    let bit_width = ty.primitive_size(fn_cx.tcx).bits();
+
+    let amount = if int128::is_int128(rhs_ty) {
+        let context = &mut fn_cx.ongoing_codegen.context;
+        int128::truncate(context, rhs, &CType::UInt(crate::ty::CUIntTy::UInt32))
+    } else {
+        rhs
+    };
+    let amount = Expression::BinaryOp {
+        op: BinOpType::And,
+        lhs: Box::new(amount),
+        rhs: Box::new(Expression::const_int(bit_width as i128 - 1)),
+    };
+
+    if int128::is_int128(ty) {
+        let context = &mut fn_cx.ongoing_codegen.context;
+        return int128::handle_binary_op(context, op, lhs, amount, ty, &fn_cx.tcx.types.u32);
+    }
+
+    match op {
+        BinOpType::Shl => Expression::wrapping(op, lhs, amount, &c_ty),
+        BinOpType::Shr if ty.is_signed() => {
+            // ~a is not negative for negative a, so only non negative values are shifted
+            let name = "codegenc_ashr(a, n)".to_string();
+            let context = &mut fn_cx.ongoing_codegen.context;
+            if !context.has_define_with_name(&name) {
+                context.get_mut_defines().push(CDefine::new(
+                    name,
+                    "((a) < 0 ? ~(~(a) >> (n)) : (a) >> (n))".to_string(),
+                ));
+            }
+
+            Expression::Cast {
+                ty: c_ty,
+                value: Box::new(Expression::FnCall {
+                    function: Expression::constant(&"codegenc_ashr".to_string()),
+                    args: vec![lhs, amount],
+                }),
+            }
+        }
+        _ => Expression::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(amount),
+        },
+    }
+}
+
+/// The remainder of floats, which C has no operator for
+fn handle_float_rem<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    lhs: Expression,
+    rhs: Expression,
+    ty: &Ty<'tcx>,
+) -> Expression {
+    let TyKind::Float(float_ty) = ty.kind() else {
+        panic!("Expected float type, found {:?}", ty);
+    };
+    let function = math_fn_name(&format!("fmod{}", float_ty.name_str())).unwrap();
+
+    Expression::FnCall {
+        function: Expression::constant(&function),
+        args: vec![lhs, rhs],
+    }
+}
+
+/// Offsets a pointer by a number of elements of its pointee.
+/// The arithmetic is done on bytes, since the C pointee type does not always match the Rust one
+/// (e.g. `void*` data pointers of fat pointers)
+fn handle_offset<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    ptr: Expression,
+    count: Expression,
+    ptr_ty: &Ty<'tcx>,
+) -> Expression {
+    let ptr_ty = fn_cx.monomorphize(*ptr_ty);
+    let pointee_ty = ptr_ty
+        .builtin_deref(true)
+        .unwrap_or_else(|| panic!("Offset on a non pointer type: {:?}", ptr_ty));
+
+    let pointee_size = fn_cx
+        .tcx
+        .layout_of(ParamEnv::reveal_all().and(pointee_ty))
+        .unwrap()
+        .size
+        .bytes();
+
+    // offsetting a pointer to a ZST never moves it
+    if pointee_size == 0 {
+        return ptr;
+    }
+
+    let byte_offset = match pointee_size {
+        1 => count,
+        size => Expression::BinaryOp {
+            op: BinOpType::Mul,
+            lhs: Box::new(count),
+            rhs: Box::new(Expression::const_int(size as i128)),
+        },
+    };
+
+    Expression::Cast {
+        ty: fn_cx.rust_to_c_type(&ptr_ty),
+        value: Box::new(Expression::BinaryOp {
+            op: BinOpType::Add,
+            lhs: Box::new(Expression::Cast {
+                ty: CType::Pointer(Box::new(CType::Int(CIntTy::Int8))),
+                value: Box::new(ptr),
+            }),
+            rhs: Box::new(byte_offset),
+        }),
+    }
+}
+
+/// References to unsized places are built from the fat pointer they were dereferenced from
+pub fn handle_unsized_ref<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    place: &Place<'tcx>,
+) -> Option<Expression> {
+    let place_ty = fn_cx
+        .monomorphize(place.ty(&fn_cx.mir.local_decls, fn_cx.tcx))
+        .ty;
+
+    if place_ty.is_sized(fn_cx.tcx, ParamEnv::reveal_all()) {
+        return None;
+    }
+
+    let (base, proj) = place.last_projection()?;
+    let base = Place {
+        local: base.local,
+        projection: fn_cx.tcx.mk_place_elems(base.projection),
+    };
+
+    let Expression::Variable { local, access } = handle_place(fn_cx, &base) else {
+        panic!("Expected place to be a variable");
+    };
+
+    match proj {
+        // reborrow, e.g. &(*_1)
+        ProjectionElem::Deref => {
+            let mut fat_ptr_access = access;
+            // drop the FatPtrDereference
+            fat_ptr_access.pop();
+
+            Some(Expression::Variable {
+                local,
+                access: fat_ptr_access,
+            })
+        }
+
+        // e.g. &(*_1)[1:2], the base is a dereferenced slice
+        ProjectionElem::Subslice { from, to, from_end } => {
+            assert!(from_end, "Unsized subslice of an array: {:?}", place);
+
+            let mut meta_access = access.clone();
+            meta_access.pop();
+            meta_access.extend(vec![
+                VariableAccess::Field {
+                    name: FAT_PTR_META_FIELD.to_string(),
+                },
+                VariableAccess::Cast {
+                    ty: fn_cx.rust_to_c_type(&fn_cx.tcx.types.usize),
+                },
+            ]);
+
+            // the base access ends with a FatPtrDereference to a pointer to the element type
+            let data = Expression::BinaryOp {
+                op: BinOpType::Add,
+                lhs: Box::new(Expression::Variable { local, access }),
+                rhs: Box::new(Expression::const_int(from as i128)),
+            };
+
+            let len = Expression::BinaryOp {
+                op: BinOpType::Sub,
+                lhs: Box::new(Expression::Variable {
+                    local,
+                    access: meta_access,
+                }),
+                rhs: Box::new(Expression::const_int((from + to) as i128)),
+            };
+
+            Some(Expression::fatptr(
+                data,
+                Expression::Cast {
+                    ty: CType::Pointer(Box::new(CType::Void)),
+                    value: Box::new(len),
+                },
+            ))
+        }
+
+        _ => {
+            warn!("Unhandled reference to unsized place: {:?}", place);
+            None
+        }
+    }
+}
+
 fn handle_assign<'tcx, 'ccx>(
     fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     c_fn: &CFunction,
@@ -392,7 +903,8 @@ fn handle_assign<'tcx, 'ccx>(
             debug!("Assign BINARY OP: {:?}", op);
             let lhs = handle_operand(fn_cx, &operands.0);
             let rhs = handle_operand(fn_cx, &operands.1);
-            let ty = operands.0.ty(&fn_cx.mir.local_decls, fn_cx.tcx);
+            let ty = fn_cx.monomorphize(operands.0.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
+            let rhs_ty = fn_cx.monomorphize(operands.1.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
 
             let place_ty = fn_cx.ty_for_local(place.local);
 
@@ -400,10 +912,34 @@ fn handle_assign<'tcx, 'ccx>(
                 BinOp::AddWithOverflow | BinOp::SubWithOverflow | BinOp::MulWithOverflow => {
                     handle_checked_op(fn_cx, op.into(), lhs, rhs, &ty, &place_ty)
                 }
-                _ => Expression::BinaryOp {
-                    op: op.into(),
-                    lhs: Box::new(lhs),
-                    rhs: Box::new(rhs),
+                BinOp::Cmp => handle_cmp(fn_cx, lhs, rhs, &ty, &place_ty),
+                BinOp::Offset => handle_offset(fn_cx, lhs, rhs, &ty),
+                BinOp::Shl | BinOp::ShlUnchecked | BinOp::Shr | BinOp::ShrUnchecked => {
+                    handle_shift(fn_cx, op.into(), lhs, rhs, &ty, &rhs_ty)
+                }
+                BinOp::Rem if ty.is_floating_point() => handle_float_rem(fn_cx, lhs, rhs, &ty),
+                BinOp::Div | BinOp::Rem if ty.is_integral() && !int128::is_int128(&ty) => {
+                    handle_div_rem(fn_cx, op.into(), lhs, rhs, &ty)
+                }
+                _ if int128::is_int128(&ty) || int128::is_int128(&rhs_ty) => {
+                    let context = &mut fn_cx.ongoing_codegen.context;
+                    match BinOpType::from(op) {
+                        op @ (BinOpType::Add | BinOpType::Sub | BinOpType::Mul) => {
+                            int128::wrapping(context, op, lhs, rhs, &ty)
+                        }
+                        op => int128::handle_binary_op(context, op, lhs, rhs, &ty, &rhs_ty),
+                    }
+                }
+                _ => match BinOpType::from(op) {
+                    op @ (BinOpType::Add | BinOpType::Sub | BinOpType::Mul) => {
+                        let c_ty = fn_cx.rust_to_c_type(&ty);
+                        Expression::wrapping(op, lhs, rhs, &c_ty)
+                    }
+                    op => Expression::BinaryOp {
+                        op,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    },
                 },
             }
         }
@@ -413,20 +949,36 @@ fn handle_assign<'tcx, 'ccx>(
                 let ty = operand.ty(&fn_cx.mir.local_decls, fn_cx.tcx);
                 let operand = handle_operand(fn_cx, operand);
 
-                Expression::UnaryOp {
-                    op: match ty.kind() {
-                        TyKind::Bool => crate::expression::UnaryOpType::Not,
-                        _ => crate::expression::UnaryOpType::BitNot,
-                    },
-                    val: Box::new(operand),
+                if int128::is_int128(&ty) {
+                    int128::handle_unary_op(
+                        &mut fn_cx.ongoing_codegen.context,
+                        crate::expression::UnaryOpType::BitNot,
+                        operand,
+                    )
+                } else {
+                    Expression::UnaryOp {
+                        op: match ty.kind() {
+                            TyKind::Bool => crate::expression::UnaryOpType::Not,
+                            _ => crate::expression::UnaryOpType::BitNot,
+                        },
+                        val: Box::new(operand),
+                    }
                 }
             }
 
             rustc_middle::mir::UnOp::Neg => {
+                let ty = fn_cx.monomorphize(operand.ty(&fn_cx.mir.local_decls, fn_cx.tcx));
                 let operand = handle_operand(fn_cx, operand);
-                Expression::UnaryOp {
-                    op: crate::expression::UnaryOpType::Neg,
-                    val: Box::new(operand),
+
+                if int128::is_int128(&ty) {
+                    int128::handle_unary_op(
+                        &mut fn_cx.ongoing_codegen.context,
+                        crate::expression::UnaryOpType::Neg,
+                        operand,
+                    )
+                } else {
+                    let c_ty = fn_cx.rust_to_c_type(&ty);
+                    Expression::wrapping_neg(operand, &c_ty)
                 }
             }
             _ => {
@@ -443,8 +995,10 @@ fn handle_assign<'tcx, 'ccx>(
 
         Rvalue::Ref(_region, _kind, place) => {
             debug!("Assign REF: {:?}", place);
-            let place: Expression = handle_place(fn_cx, place);
-            if let Expression::Variable { local, access } = place {
+
+            if let Some(fat_ptr) = handle_unsized_ref(fn_cx, place) {
+                fat_ptr
+            } else if let Expression::Variable { local, access } = handle_place(fn_cx, place) {
                 let mut new_access = access.clone();
                 new_access.push(VariableAccess::Reference);
                 Expression::Variable {
@@ -546,7 +1100,7 @@ fn handle_assign<'tcx, 'ccx>(
 }
 
 pub fn handle_constant<'tcx, 'ccx>(
-    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
     const_op: &ConstOperand<'tcx>,
 ) -> Expression {
     let constant = &const_op.const_;
@@ -560,9 +1114,9 @@ pub fn handle_constant<'tcx, 'ccx>(
 }
 
 fn handle_const_value<'tcx, 'ccx>(
-    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
-    val: &ConstValue,
-    ty: &Ty,
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    val: &ConstValue<'tcx>,
+    ty: &Ty<'tcx>,
 ) -> Expression {
     let _span = span!(tracing::Level::DEBUG, "handle_const_value").entered();
     debug!("Const value: {:?}, with type: {:?}", val, ty);
@@ -570,6 +1124,17 @@ fn handle_const_value<'tcx, 'ccx>(
     match val {
         rustc_middle::mir::ConstValue::Scalar(scalar) => match scalar {
             rustc_const_eval::interpret::Scalar::Int(_i) => match ty.kind() {
+                // C has no 128-bit integer literals
+                rustc_middle::ty::TyKind::Int(_) | rustc_middle::ty::TyKind::Uint(_)
+                    if int128::is_int128(ty) =>
+                {
+                    let c_ty = fn_cx.rust_to_c_type(ty);
+                    return int128::literal(
+                        &mut fn_cx.ongoing_codegen.context,
+                        utils::scalar_to_u128(&scalar),
+                        &c_ty,
+                    );
+                }
                 rustc_middle::ty::TyKind::Int(_) => {
                     return Expression::Constant {
                         value: format!("{}", utils::scalar_to_u128(&scalar)),
@@ -617,10 +1182,38 @@ fn handle_const_value<'tcx, 'ccx>(
 
             rustc_const_eval::interpret::Scalar::Ptr(ptr, size) => {
                 debug!("Ptr: {:?}, size: {:?}", ptr, size);
-                let alloc_id = ptr.provenance.get_alloc_id().unwrap();
+                let (provenance, offset) = ptr.into_parts();
+                let alloc_id = provenance.get_alloc_id().unwrap();
                 let alloc = fn_cx.tcx.global_alloc(alloc_id);
 
-                let const_alloc = alloc.unwrap_memory();
+                // the static is emitted as a struct of its bytes, so cast to the expected type
+                if let GlobalAlloc::Static(_) = alloc {
+                    return Expression::Cast {
+                        ty: fn_cx.rust_to_c_type(ty),
+                        value: Box::new(alloc::offset_by(
+                            fn_cx.handle_global_decl(alloc_id),
+                            offset.bytes(),
+                        )),
+                    };
+                }
+
+                let const_alloc = match alloc {
+                    GlobalAlloc::Memory(const_alloc)
+                        if const_alloc.inner().provenance().ptrs().is_empty() =>
+                    {
+                        const_alloc
+                    }
+                    // allocations holding pointers are emitted along with what they point to
+                    _ => {
+                        let address =
+                            alloc::offset_by(fn_cx.handle_global_decl(alloc_id), offset.bytes());
+
+                        return Expression::Cast {
+                            ty: fn_cx.rust_to_c_type(ty),
+                            value: Box::new(address),
+                        };
+                    }
+                };
                 let inner = const_alloc.inner();
                 let alloc_bytes: Vec<u8> = inner
                     .inspect_with_uninit_and_ptr_outside_interpreter(0..inner.len())
@@ -648,6 +1241,18 @@ fn handle_const_value<'tcx, 'ccx>(
         rustc_middle::mir::ConstValue::Slice { data, meta } => {
             debug!("Const Alloc: {:?} meta: {:?}", data, meta);
 
+            // e.g. &[&str], whose elements point to other allocations
+            if !data.inner().provenance().ptrs().is_empty() {
+                let alloc_id = fn_cx.tcx.reserve_and_set_memory_alloc(data);
+                return Expression::fatptr(
+                    fn_cx.handle_global_decl(alloc_id),
+                    Expression::Cast {
+                        ty: CType::Pointer(Box::new(CType::Void)),
+                        value: Box::new(Expression::const_int(*meta as i128)),
+                    },
+                );
+            }
+
             let inner_alloc = data.inner();
 
             let alloc_bytes: Vec<u8> = inner_alloc
@@ -671,9 +1276,52 @@ fn handle_const_value<'tcx, 'ccx>(
             )
         }
 
+        // fat pointers other than slices, e.g. &dyn Trait, are kept in memory as two pointer sized halves
+        rustc_middle::mir::ConstValue::Indirect { alloc_id, offset }
+            if fn_cx.rust_to_c_type(ty) == CType::FatPointer =>
+        {
+            let alloc = fn_cx.tcx.global_alloc(*alloc_id).unwrap_memory();
+            let ptr_size = fn_cx.tcx.data_layout.pointer_size;
+
+            Expression::fatptr(
+                const_fat_ptr_half(fn_cx, alloc, *offset),
+                const_fat_ptr_half(fn_cx, alloc, *offset + ptr_size),
+            )
+        }
+
         _ => {
             warn!("Unhandled constant: {:?}", val);
             return Expression::NoOp {};
         }
     }
 }
+
+// the data pointer or metadata of a constant fat pointer, as a void*
+fn const_fat_ptr_half<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    alloc: ConstAllocation<'tcx>,
+    offset: Size,
+) -> Expression {
+    let ptr_size = fn_cx.tcx.data_layout.pointer_size;
+    let scalar = alloc
+        .inner()
+        .read_scalar(&fn_cx.tcx, alloc_range(offset, ptr_size), true)
+        .expect("Expected a pointer sized scalar in constant fat pointer");
+
+    let value = match scalar {
+        Scalar::Ptr(ptr, _) => {
+            let (provenance, ptr_offset) = ptr.into_parts();
+            alloc::offset_by(
+                fn_cx.handle_global_decl(provenance.get_alloc_id().unwrap()),
+                ptr_offset.bytes(),
+            )
+        }
+        // lengths of slices in a struct tail
+        Scalar::Int(int) => Expression::const_int(int.to_uint(int.size()) as i128),
+    };
+
+    Expression::Cast {
+        ty: CType::Pointer(Box::new(CType::Void)),
+        value: Box::new(value),
+    }
+}
@@ -1,7 +1,10 @@
 use crate::crepr::Representable;
 use crate::definition::CVarDef;
 use crate::fatptr::FAT_PTR_NAME;
+use crate::float;
 use crate::function::CodegenFunctionCx;
+use crate::int128;
+use crate::simd;
 use crate::structure::{CComposite, CStructDef, CTaggedUnionDef};
 use crate::utils;
 use rustc_hir::def_id::DefId;
@@ -33,6 +36,18 @@ pub enum CType {
     Pointer(Box<CType>),
     Array(Box<CType>, usize),
     FunctionPtr(Box<CFuncPtrInfo>),
+    // types provided by the C library that are only referred to by name, e.g. jmp_buf
+    Opaque(String),
+    // C11 _Atomic qualified type, used for the targets of atomic operations
+    Atomic(Box<CType>),
+    // volatile qualified type, accesses through pointers to it are never elided
+    Volatile(Box<CType>),
+    // C11 _Thread_local storage, for globals with a separate value per thread
+    ThreadLocal(Box<CType>),
+    // weak globals, the definitions of all crates are merged into one at link time
+    Weak(Box<CType>),
+    // element type and lane count of a simd vector, only used as the field of simd structs
+    Vector(Box<CType>, usize),
 }
 
 impl CType {
@@ -170,6 +185,46 @@ impl Representable for CType {
                     write!(f, "{}[{}]", var_name, size)
                 }
             }
+            CType::Opaque(opaque_name) => {
+                let ptrs = "*".repeat(context.get_ptr_count().into());
+                let c_type = format!("{opaque_name}{ptrs}");
+                match context.get_variable_name_option() {
+                    Some(name) => write!(f, "{c_type} {name}"),
+                    None => write!(f, "{c_type}"),
+                }
+            }
+            CType::Atomic(ty) => {
+                write!(f, "_Atomic ")?;
+                ty.repr(f, context)
+            }
+            // the qualifier goes after the stars of a pointer pointee, so it applies to the
+            // pointee itself, e.g. uint8_t* volatile* rather than volatile uint8_t**
+            CType::Volatile(ty) if matches!(**ty, CType::Pointer(_) | CType::FatPointer) => {
+                let ptrs = "*".repeat(context.get_ptr_count().into());
+                let var_name = context.get_variable_name_option();
+                ty.repr(f, context)?;
+                match var_name {
+                    Some(name) => write!(f, " volatile{ptrs} {name}"),
+                    None => write!(f, " volatile{ptrs}"),
+                }
+            }
+            CType::Volatile(ty) => {
+                write!(f, "volatile ")?;
+                ty.repr(f, context)
+            }
+            CType::ThreadLocal(ty) => {
+                write!(f, "_Thread_local ")?;
+                ty.repr(f, context)
+            }
+            CType::Weak(ty) => {
+                write!(f, "__attribute__((weak)) ")?;
+                ty.repr(f, context)
+            }
+            // declared through a macro, since the vector extension is not available everywhere
+            CType::Vector(elem, lanes) => {
+                let var_name = context.get_variable_name_option().unwrap_or_default();
+                write!(f, "{}({})", simd::vector_type_name(elem, *lanes), var_name)
+            }
             CType::FunctionPtr(func_info) => {
                 let var_name = match context.get_variable_name_option() {
                     Some(name) => name,
@@ -214,8 +269,8 @@ impl CIntTy {
             CIntTy::Int16 => "int16_t",
             CIntTy::Int32 => "int32_t",
             CIntTy::Int64 => "int64_t",
-            // Might cause trouble on some gcc versions
-            CIntTy::Int128 => "__int128_t",
+            // defined as __int128 or the struct fallback, see int128.rs
+            CIntTy::Int128 => int128::I128_NAME,
         }
     }
 
@@ -258,8 +313,8 @@ impl CUIntTy {
             CUIntTy::UInt16 => "uint16_t",
             CUIntTy::UInt32 => "uint32_t",
             CUIntTy::UInt64 => "uint64_t",
-            // Might cause trouble on some gcc versions
-            CUIntTy::UInt128 => "__uint128_t",
+            // defined as unsigned __int128 or the struct fallback, see int128.rs
+            CUIntTy::UInt128 => int128::U128_NAME,
         }
     }
 
@@ -297,11 +352,11 @@ pub enum CFloatTy {
 impl CFloatTy {
     pub fn name_str(&self) -> &'static str {
         match *self {
-            CFloatTy::Float16 => "float",
+            CFloatTy::Float16 => float::F16_NAME,
             CFloatTy::Float32 => "float",
             CFloatTy::Float64 => "double",
-            // Might cause trouble on some gcc versions
-            CFloatTy::Float128 => "__float128",
+            // long double, _Float128 or __float128, see float.rs
+            CFloatTy::Float128 => float::F128_NAME,
         }
     }
 
@@ -493,6 +548,33 @@ impl<'tcx> CodegenFunctionCx<'tcx, '_> {
             rustc_middle::ty::Adt(adt_def, generic_fields) => {
                 debug!("Adt: {:?}", adt_def.adt_kind());
                 match adt_def.adt_kind() {
+                    // #[repr(simd)] structs wrap a single vector of all their lanes
+                    rustc_middle::ty::AdtKind::Struct if adt_def.repr().simd() => {
+                        let (lanes, elem_ty) = ty.simd_size_and_type(self.tcx);
+                        let elem = self.rust_to_c_type(&elem_ty);
+                        simd::add_vector_define(
+                            &mut self.ongoing_codegen.context,
+                            &elem,
+                            lanes as usize,
+                        );
+
+                        let c_struct = CStructDef {
+                            name: self.composite_name(adt_def.did(), generic_fields),
+                            fields: vec![CVarDef::new(
+                                0,
+                                CType::WRAPPER_FIELD_NAME.to_string(),
+                                CType::Vector(Box::new(elem), lanes as usize),
+                            )],
+                        };
+
+                        let struct_info = self
+                            .ongoing_codegen
+                            .context
+                            .add_composite(&CComposite::Struct(c_struct));
+
+                        return CType::Struct(struct_info);
+                    }
+
                     rustc_middle::ty::AdtKind::Struct => {
                         let c_struct = CStructDef {
                             name: self.composite_name(adt_def.did(), generic_fields),
@@ -662,11 +744,25 @@ impl<'tcx> CodegenFunctionCx<'tcx, '_> {
             rustc_middle::ty::Never => CType::Unit,
 
             rustc_middle::ty::RawPtr(ty, _mutability) => {
+                // raw pointers to unsized types carry metadata like references do
+                if !ty.is_sized(self.tcx, ParamEnv::reveal_all()) {
+                    return CType::FatPointer {};
+                }
                 let c_ty = self.rust_to_c_type(ty);
 
                 CType::Pointer(Box::new(c_ty))
             }
 
+            rustc_middle::ty::Float(rustc_middle::ty::FloatTy::F128) => {
+                float::add_f128_types(&mut self.ongoing_codegen.context);
+                CType::from(ty)
+            }
+
+            rustc_middle::ty::Int(_) | rustc_middle::ty::Uint(_) if int128::is_int128(ty) => {
+                int128::add_int128_types(&mut self.ongoing_codegen.context);
+                CType::from(ty)
+            }
+
             _ => CType::from(ty),
         }
     }
@@ -0,0 +1,156 @@
+use crate::expression::{BinOpType, Expression, UnaryOpType, VariableAccess};
+use crate::fatptr::{FAT_PTR_DATA_FIELD, FAT_PTR_META_FIELD};
+use crate::function::CodegenFunctionCx;
+use crate::ty::CType;
+use crate::vtable;
+use rustc_middle::ty::{ParamEnv, Ty, TyKind};
+use tracing::{debug, debug_span};
+
+/// Coerces `value` from `source` to `target`, where the pointee of a pointer becomes unsized.
+/// The pointer is either `value` itself, or nested in the fields of a smart pointer like `Box` or `Rc`
+pub fn coerce_unsized<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    value: Expression,
+    source: Ty<'tcx>,
+    target: Ty<'tcx>,
+) -> Expression {
+    let _span = debug_span!("coerce_unsized").entered();
+    debug!("Unsize {:?} -> {:?}", source, target);
+
+    match (source.kind(), target.kind()) {
+        (TyKind::Ref(..) | TyKind::RawPtr(..), TyKind::Ref(..) | TyKind::RawPtr(..)) => {
+            let source_pointee = source.builtin_deref(true).unwrap();
+            let target_pointee = target.builtin_deref(true).unwrap();
+
+            // upcasting a trait object keeps its data pointer and starts from its vtable
+            let (data, old_meta) = match fn_cx.rust_to_c_type(&source) {
+                CType::FatPointer => (
+                    field(value.clone(), FAT_PTR_DATA_FIELD.to_string(), 0),
+                    Some(field(value, FAT_PTR_META_FIELD.to_string(), 1)),
+                ),
+                _ => (value, None),
+            };
+            let meta = unsized_info(fn_cx, source_pointee, target_pointee, old_meta);
+
+            Expression::fatptr(data, meta)
+        }
+
+        // CoerceUnsized structs have a single field that is coerced, the rest are copied
+        (TyKind::Adt(source_def, source_args), TyKind::Adt(target_def, target_args)) => {
+            assert_eq!(source_def, target_def);
+            let (CType::Struct(source_info), CType::Struct(target_info)) =
+                (fn_cx.rust_to_c_type(&source), fn_cx.rust_to_c_type(&target))
+            else {
+                panic!("Expected structs for unsizing {:?} to {:?}", source, target);
+            };
+
+            let mut fields = Vec::new();
+            for (idx, field_def) in source_def.non_enum_variant().fields.iter().enumerate() {
+                let name = fn_cx
+                    .ongoing_codegen
+                    .context
+                    .get_field_name_for_struct(&source_info, idx)
+                    .unwrap();
+                let field_value = field(value.clone(), name, idx);
+
+                let source_field = fn_cx.tcx.normalize_erasing_regions(
+                    ParamEnv::reveal_all(),
+                    field_def.ty(fn_cx.tcx, source_args),
+                );
+                let target_field = fn_cx.tcx.normalize_erasing_regions(
+                    ParamEnv::reveal_all(),
+                    field_def.ty(fn_cx.tcx, target_args),
+                );
+
+                if source_field == target_field {
+                    fields.push(field_value);
+                } else {
+                    fields.push(coerce_unsized(
+                        fn_cx,
+                        field_value,
+                        source_field,
+                        target_field,
+                    ));
+                }
+            }
+
+            Expression::Struct {
+                name: Box::new(Expression::Constant {
+                    value: target_info.name.clone(),
+                }),
+                fields,
+            }
+        }
+
+        _ => panic!(
+            "Unhandled unsize operation from {:?} to {:?}",
+            source, target
+        ),
+    }
+}
+
+/// The metadata of a pointer to `target`, pointing to a value of `source`.
+/// The unsized part is the tail of both types, e.g. `Wrapper<[u8; 4]>` to `Wrapper<[u8]>`
+fn unsized_info<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    source: Ty<'tcx>,
+    target: Ty<'tcx>,
+    old_meta: Option<Expression>,
+) -> Expression {
+    let (source_tail, target_tail) =
+        fn_cx
+            .tcx
+            .struct_lockstep_tails_erasing_lifetimes(source, target, ParamEnv::reveal_all());
+
+    match (source_tail.kind(), target_tail.kind()) {
+        (TyKind::Array(_, len), TyKind::Slice(_)) => Expression::Constant {
+            value: len
+                .try_eval_target_usize(fn_cx.tcx, ParamEnv::reveal_all())
+                .expect("Expected array size to be known")
+                .to_string(),
+        },
+
+        (TyKind::Dynamic(source_data, ..), TyKind::Dynamic(target_data, ..)) => {
+            let old_meta = old_meta.expect("Expected the vtable of the trait object to upcast");
+            if source_data.principal_def_id() == target_data.principal_def_id() {
+                return old_meta;
+            }
+
+            match fn_cx
+                .tcx
+                .vtable_trait_upcasting_coercion_new_vptr_slot((source_tail, target_tail))
+            {
+                // the vtable of the supertrait is an entry in the vtable of the subtrait
+                Some(entry_idx) => Expression::UnaryOp {
+                    op: UnaryOpType::Deref,
+                    val: Box::new(Expression::BinaryOp {
+                        op: BinOpType::Add,
+                        lhs: Box::new(Expression::Cast {
+                            ty: CType::Pointer(Box::new(CType::Pointer(Box::new(CType::Void)))),
+                            value: Box::new(old_meta),
+                        }),
+                        rhs: Box::new(Expression::const_int(entry_idx as i128)),
+                    }),
+                },
+                // the supertrait's vtable is a prefix of the subtrait's
+                None => old_meta,
+            }
+        }
+
+        (_, TyKind::Dynamic(..)) => vtable::vtable_ptr(fn_cx, source_tail, target_tail),
+
+        _ => panic!("Unhandled unsized tail from {:?} to {:?}", source, target),
+    }
+}
+
+/// Field `idx` named `name` of `value`, which is either a place or a constant struct literal
+fn field(value: Expression, name: String, idx: usize) -> Expression {
+    match value {
+        Expression::Variable { local, mut access } => {
+            access.push(VariableAccess::Field { name });
+            Expression::Variable { local, access }
+        }
+        Expression::Struct { mut fields, .. } => fields.swap_remove(idx),
+        _ => panic!("Expected variable or struct expression, found {:?}", value),
+    }
+}
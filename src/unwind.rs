@@ -0,0 +1,306 @@
+use std::env;
+
+use rustc_middle::mir::{Body, UnwindAction};
+
+use crate::{
+    base::Context,
+    bb::{BasicBlock, BasicBlockIdentifier},
+    definition::{CVarDecl, CVarDef},
+    expression::Expression,
+    function::CFunction,
+    stmt::Statement,
+    structure::{CComposite, CStructDef},
+    ty::{CCompositeInfo, CFuncPtrInfo, CIntTy, CType, CUIntTy},
+};
+
+pub const UNWIND_FRAME_NAME: &str = "codegenc_unwind_frame";
+pub const UNWIND_TOP_NAME: &str = "codegenc_unwind_top";
+pub const RESUME_UNWIND_FN_NAME: &str = "codegenc_resume_unwind";
+pub const TRY_FN_NAME: &str = "codegenc_try";
+
+// name of the frame local in functions with landing pads
+const FRAME_LOCAL_NAME: &str = "unwind_frame";
+
+/// Unwinding is opt-in, panics abort unless C_CODEGEN_PANIC=UNWIND is set
+pub fn is_enabled() -> bool {
+    match env::var("C_CODEGEN_PANIC") {
+        Ok(val) => val == "UNWIND",
+        Err(_) => false,
+    }
+}
+
+fn constant(value: &str) -> Box<Expression> {
+    Expression::constant(&value.to_string())
+}
+
+fn call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FnCall {
+        function: constant(function),
+        args,
+    }
+}
+
+/// Starts unwinding, or continues it at the next landing pad
+pub fn resume_unwind() -> Expression {
+    if is_enabled() {
+        call(RESUME_UNWIND_FN_NAME, vec![])
+    } else {
+        call("abort", vec![])
+    }
+}
+
+fn frame_type(context: &Context) -> CType {
+    let frame = context
+        .get_structs()
+        .iter()
+        .position(|s| s.get_name() == UNWIND_FRAME_NAME)
+        .expect("Unwind frame struct not found, is the unwind runtime built?");
+
+    CType::Struct(CCompositeInfo::new(&UNWIND_FRAME_NAME.to_string(), frame))
+}
+
+/// Builds the cleanup-frame chain used for unwinding, if it wasn't built yet.
+/// Every landing pad pushes a frame holding a jmp_buf, unwinding longjmps to the topmost frame
+pub fn build_unwind_runtime(context: &mut Context) {
+    if context.exists_header_fn_with_name(RESUME_UNWIND_FN_NAME) {
+        return;
+    }
+
+    // typedef struct { jmp_buf buf; void* prev; } codegenc_unwind_frame;
+    context.add_composite(&CComposite::Struct(CStructDef {
+        name: UNWIND_FRAME_NAME.to_string(),
+        fields: vec![
+            CVarDef::new(0, "buf".to_string(), CType::Opaque("jmp_buf".to_string())),
+            CVarDef::new(1, "prev".to_string(), CType::Pointer(Box::new(CType::Void))),
+        ],
+    }));
+
+    let frame_ty = frame_type(context);
+
+    // every thread unwinds through its own frame chain, shared by all crates of the program.
+    // Each crate defines the runtime weakly, so the linker keeps a single copy of it
+    context.add_global(CVarDecl::new(
+        CVarDef::new(
+            0,
+            UNWIND_TOP_NAME.to_string(),
+            CType::Weak(Box::new(CType::ThreadLocal(Box::new(CType::Pointer(
+                Box::new(frame_ty.clone()),
+            ))))),
+        ),
+        Some(constant("NULL")),
+    ));
+
+    let mut resume_unwind_fn = resume_unwind_fn(&frame_ty);
+    resume_unwind_fn.set_weak();
+    let mut try_fn = try_fn(&frame_ty);
+    try_fn.set_weak();
+
+    context
+        .get_mut_header_functions()
+        .extend([resume_unwind_fn, try_fn]);
+}
+
+/// Lowers catch_unwind(try_fn, data, catch_fn), try_fn runs in a frame of the unwind runtime.
+/// Without unwinding panics abort, so nothing is caught and try_fn is called directly
+pub fn handle_catch_unwind(
+    context: &mut Context,
+    args: Vec<Expression>,
+    destination: Expression,
+) -> Vec<Statement> {
+    if !is_enabled() {
+        return vec![
+            Statement::from_expression(Expression::FnCall {
+                function: Box::new(args[0].clone()),
+                args: vec![args[1].clone()],
+            }),
+            Statement::from_expression(destination.assign(Box::new(Expression::const_int(0)))),
+        ];
+    }
+
+    build_unwind_runtime(context);
+
+    vec![Statement::from_expression(
+        destination.assign(Box::new(call(TRY_FN_NAME, args))),
+    )]
+}
+
+// void codegenc_resume_unwind() {
+//     codegenc_unwind_frame* frame = codegenc_unwind_top;
+//     if (frame == NULL) abort();
+//     codegenc_unwind_top = frame->prev;
+//     longjmp(frame->buf, 1);
+// }
+fn resume_unwind_fn(frame_ty: &CType) -> CFunction {
+    let mut c_fn = CFunction::new(RESUME_UNWIND_FN_NAME.to_string(), CType::Void);
+
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(
+            0,
+            "frame".to_string(),
+            CType::Pointer(Box::new(frame_ty.clone())),
+        ),
+        Some(constant(UNWIND_TOP_NAME)),
+    ));
+
+    let mut bb = BasicBlock::new(BasicBlockIdentifier(0));
+    // unwinding without a landing pad left
+    bb.push(Statement::from_expression(Expression::If {
+        cond: Expression::unbvari(0).equ(constant("NULL")),
+        then: Box::new(call("abort", vec![])),
+    }));
+    bb.push(Statement::from_expression(
+        Expression::Constant {
+            value: UNWIND_TOP_NAME.to_string(),
+        }
+        .assign(constant("frame->prev")),
+    ));
+    bb.push(Statement::from_expression(call(
+        "longjmp",
+        vec![*constant("frame->buf"), Expression::const_int(1)],
+    )));
+
+    c_fn.push_bb(bb);
+    c_fn
+}
+
+// int32_t codegenc_try(void (*try_fn)(uint8_t*), uint8_t* data, void (*catch_fn)(uint8_t*, uint8_t*)) {
+//     codegenc_unwind_frame frame;
+//     frame.prev = codegenc_unwind_top;
+//     codegenc_unwind_top = &frame;
+//     if (setjmp(frame.buf)) goto bb1;
+//     try_fn(data);
+//     codegenc_unwind_top = frame.prev;
+//     return 0;
+// bb1:
+//     catch_fn(data, NULL);
+//     return 1;
+// }
+fn try_fn(frame_ty: &CType) -> CFunction {
+    let data_ty = CType::Pointer(Box::new(CType::UInt(CUIntTy::UInt8)));
+
+    let mut c_fn = CFunction::new(TRY_FN_NAME.to_string(), CType::Int(CIntTy::Int32));
+    c_fn.add_signature_var(CVarDef::new(
+        0,
+        "try_fn".to_string(),
+        CType::FunctionPtr(Box::new(CFuncPtrInfo {
+            args: vec![data_ty.clone()],
+            ret: Box::new(CType::Void),
+        })),
+    ));
+    c_fn.add_signature_var(CVarDef::new(1, "data".to_string(), data_ty.clone()));
+    c_fn.add_signature_var(CVarDef::new(
+        2,
+        "catch_fn".to_string(),
+        CType::FunctionPtr(Box::new(CFuncPtrInfo {
+            args: vec![data_ty.clone(), data_ty],
+            ret: Box::new(CType::Void),
+        })),
+    ));
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(3, "frame".to_string(), frame_ty.clone()),
+        None,
+    ));
+
+    let mut bb0 = BasicBlock::new(BasicBlockIdentifier(0));
+    bb0.statements.extend(push_frame(
+        "frame",
+        Expression::Goto {
+            target: BasicBlockIdentifier(1),
+        },
+    ));
+    bb0.push(Statement::from_expression(Expression::FnCall {
+        function: Expression::vari(0),
+        args: vec![Expression::unbvari(1)],
+    }));
+    bb0.push(pop_frame("frame"));
+    bb0.push(Statement::from_expression(Expression::Return {
+        value: Box::new(Expression::const_int(0)),
+    }));
+
+    // the payload of C unwinds is always NULL
+    let mut bb1 = BasicBlock::new(BasicBlockIdentifier(1));
+    bb1.push(Statement::from_expression(Expression::FnCall {
+        function: Expression::vari(2),
+        args: vec![Expression::unbvari(1), *constant("NULL")],
+    }));
+    bb1.push(Statement::from_expression(Expression::Return {
+        value: Box::new(Expression::const_int(1)),
+    }));
+
+    c_fn.push_bb(bb0);
+    c_fn.push_bb(bb1);
+    c_fn
+}
+
+/// Pushes `frame` onto the frame chain, `handler` runs when something unwinds into it
+fn push_frame(frame: &str, handler: Expression) -> Vec<Statement> {
+    vec![
+        Statement::from_expression(
+            Expression::Constant {
+                value: format!("{frame}.prev"),
+            }
+            .assign(constant(UNWIND_TOP_NAME)),
+        ),
+        Statement::from_expression(
+            Expression::Constant {
+                value: UNWIND_TOP_NAME.to_string(),
+            }
+            .assign(Box::new(Expression::Constant {
+                value: format!("&{frame}"),
+            })),
+        ),
+        Statement::from_expression(Expression::If {
+            cond: Box::new(call("setjmp", vec![*constant(&format!("{frame}.buf"))])),
+            then: Box::new(handler),
+        }),
+    ]
+}
+
+fn pop_frame(frame: &str) -> Statement {
+    Statement::from_expression(
+        Expression::Constant {
+            value: UNWIND_TOP_NAME.to_string(),
+        }
+        .assign(Box::new(Expression::Constant {
+            value: format!("{frame}.prev"),
+        })),
+    )
+}
+
+/// Functions with calls that can unwind into a cleanup block need a frame local
+pub fn needs_unwind_frame(mir: &Body<'_>) -> bool {
+    is_enabled()
+        && mir.basic_blocks.iter().any(|block_data| {
+            matches!(
+                block_data.terminator().unwind(),
+                Some(UnwindAction::Cleanup(_) | UnwindAction::Terminate(_))
+            )
+        })
+}
+
+pub fn add_unwind_frame(c_fn: &mut CFunction, context: &Context, local_id: usize) {
+    c_fn.add_var_decl(CVarDecl::new(
+        CVarDef::new(local_id, FRAME_LOCAL_NAME.to_string(), frame_type(context)),
+        None,
+    ));
+}
+
+/// Wraps the statements of a call in a landing pad for its unwind action
+pub fn with_landing_pad(unwind: &UnwindAction, call_stmts: Vec<Statement>) -> Vec<Statement> {
+    if !is_enabled() {
+        return call_stmts;
+    }
+
+    let handler = match unwind {
+        UnwindAction::Cleanup(cleanup) => Expression::Goto {
+            target: BasicBlockIdentifier(cleanup.as_usize()),
+        },
+        UnwindAction::Terminate(_) => call("abort", vec![]),
+        UnwindAction::Continue | UnwindAction::Unreachable => return call_stmts,
+    };
+
+    let mut stmts = push_frame(FRAME_LOCAL_NAME, handler);
+    stmts.extend(call_stmts);
+    stmts.push(pop_frame(FRAME_LOCAL_NAME));
+    stmts
+}
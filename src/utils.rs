@@ -1,3 +1,4 @@
+use crate::float;
 use rustc_middle::mir::interpret::Scalar;
 use rustc_middle::ty::Const;
 pub fn const_to_usize(value: &Const) -> usize {
@@ -16,21 +17,10 @@ pub fn scalar_to_u128(value: &Scalar) -> u128 {
     }
 }
 
+/// Formats a float scalar as an exact C literal
 pub fn scalar_to_float(value: &Scalar) -> String {
     match value {
-        Scalar::Int(i) => {
-            return match i.size().bytes() {
-                2 => i.to_f16().to_string(),
-
-                4 => i.to_f32().to_string(),
-
-                8 => i.to_f64().to_string(),
-
-                16 => i.to_f128().to_string(),
-
-                _ => panic!("Unsupported float size!"),
-            }
-        }
+        Scalar::Int(i) => float::literal(i.to_uint(i.size()), i.size().bits()),
         Scalar::Ptr(_, _) => panic!("Trying to get value of a pointer that is not supported!"),
     }
 }
@@ -39,3 +29,10 @@ pub fn truncate_to_size(value: u128, bytes: usize) -> u128 {
     let mask = (1 << (bytes * 8)) - 1;
     value & mask
 }
+
+/// Escapes a string for use inside a C string literal
+pub fn escape_c_string(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,324 @@
+use rustc_middle::ty::print::with_no_trimmed_paths;
+use rustc_middle::ty::{
+    self, ExistentialPredicate, ExistentialTraitRef, Instance, InstanceDef, ParamEnv, Ty, TyCtxt,
+    TyKind, VtblEntry, COMMON_VTABLE_ENTRIES,
+};
+use tracing::debug;
+
+use crate::{
+    definition::{CVarDecl, CVarDef},
+    expression::{Expression, VariableAccess},
+    fatptr::FAT_PTR_META_FIELD,
+    function::{format_fn_name, CodegenFunctionCx},
+    structure::{CComposite, CStructDef},
+    ty::{CCompositeInfo, CFuncPtrInfo, CType},
+};
+
+const DROP_IN_PLACE_FIELD: &str = "drop_in_place";
+const SIZE_FIELD: &str = "size";
+const ALIGN_FIELD: &str = "align";
+
+/// The struct of the vtables of a trait object type, with a field for each vtable entry:
+/// drop_in_place, size and align, followed by the methods and the vtables of supertraits
+pub fn vtable_type<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    dyn_ty: Ty<'tcx>,
+) -> CType {
+    let name = vtable_type_name(fn_cx, dyn_ty);
+
+    if let Some(idx) = fn_cx
+        .ongoing_codegen
+        .context
+        .get_structs()
+        .iter()
+        .position(|s| s.get_name() == name)
+    {
+        return CType::Struct(CCompositeInfo::new(&name, idx));
+    }
+
+    // the entries are the same for every implementor, so they are taken from the trait object itself
+    let fields = vtable_entries(fn_cx, dyn_ty, dyn_ty)
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            let (name, ty) = match entry {
+                VtblEntry::MetadataDropInPlace => {
+                    (DROP_IN_PLACE_FIELD.to_string(), drop_fn_type(fn_cx))
+                }
+                VtblEntry::MetadataSize => (
+                    SIZE_FIELD.to_string(),
+                    fn_cx.rust_to_c_type(&fn_cx.tcx.types.usize),
+                ),
+                VtblEntry::MetadataAlign => (
+                    ALIGN_FIELD.to_string(),
+                    fn_cx.rust_to_c_type(&fn_cx.tcx.types.usize),
+                ),
+                VtblEntry::Method(instance) => (
+                    format!("method_{idx}_{}", fn_cx.tcx.item_name(instance.def_id())),
+                    method_type(fn_cx, *instance),
+                ),
+                // methods that can't be called on trait objects
+                VtblEntry::Vacant => (format!("vacant_{idx}"), void_ptr()),
+                VtblEntry::TraitVPtr(_) => (format!("vptr_{idx}"), void_ptr()),
+            };
+            CVarDef::new(idx, name, ty)
+        })
+        .collect();
+
+    let info = fn_cx
+        .ongoing_codegen
+        .context
+        .add_composite(&CComposite::Struct(CStructDef { name, fields }));
+
+    CType::Struct(info)
+}
+
+/// A pointer to the vtable of `source_ty` as the trait object `dyn_ty`.
+/// The vtable is emitted as a global of the typed vtable struct, once per allocation
+pub fn vtable_ptr<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    source_ty: Ty<'tcx>,
+    dyn_ty: Ty<'tcx>,
+) -> Expression {
+    let TyKind::Dynamic(predicates, ..) = dyn_ty.kind() else {
+        panic!("Expected trait object, found {:?}", dyn_ty);
+    };
+    let alloc_id = fn_cx
+        .tcx
+        .vtable_allocation((source_ty, predicates.principal()));
+
+    if let Some(vtable) = fn_cx.alloc_to_c.get(&alloc_id) {
+        return vtable.clone();
+    }
+
+    let name = format!("VTABLE_{}_CRATE_{}", alloc_id.0, fn_cx.crate_num);
+    let vtable = Expression::Constant {
+        value: format!("&{name}"),
+    };
+    fn_cx.alloc_to_c.insert(alloc_id, vtable.clone());
+
+    debug!("Vtable {} of {:?} as {:?}", name, source_ty, dyn_ty);
+
+    let vtable_ty = vtable_type(fn_cx, dyn_ty);
+    let CType::Struct(info) = &vtable_ty else {
+        unreachable!("vtables are structs");
+    };
+    let field_types: Vec<CType> = fn_cx
+        .ongoing_codegen
+        .context
+        .get_struct_def(info)
+        .expect("Vtable struct not found")
+        .fields
+        .iter()
+        .map(|field| field.get_type().clone())
+        .collect();
+
+    let layout = fn_cx
+        .tcx
+        .layout_of(ParamEnv::reveal_all().and(source_ty))
+        .unwrap();
+
+    let entries = vtable_entries(fn_cx, source_ty, dyn_ty)
+        .iter()
+        .zip(field_types)
+        .map(|(entry, field_ty)| match entry {
+            VtblEntry::MetadataDropInPlace => {
+                let drop_instance = Instance::resolve_drop_in_place(fn_cx.tcx, source_ty);
+                match drop_instance.def {
+                    InstanceDef::DropGlue(_, None) => null(field_ty),
+                    _ => fn_ref(fn_cx, drop_instance, field_ty),
+                }
+            }
+            VtblEntry::MetadataSize => Expression::const_int(layout.size.bytes() as i128),
+            VtblEntry::MetadataAlign => Expression::const_int(layout.align.abi.bytes() as i128),
+            VtblEntry::Method(instance) => fn_ref(fn_cx, *instance, field_ty),
+            VtblEntry::Vacant => null(field_ty),
+            VtblEntry::TraitVPtr(trait_ref) => {
+                let super_dyn_ty = trait_object_type(fn_cx, *trait_ref);
+                vtable_ptr(fn_cx, source_ty, super_dyn_ty)
+            }
+        })
+        .collect();
+
+    fn_cx.ongoing_codegen.context.add_vtable(CVarDecl::new(
+        CVarDef::new(0, name, vtable_ty),
+        Some(Box::new(Expression::InitializerList { fields: entries })),
+    ));
+
+    vtable
+}
+
+/// The vtable of a trait object fat pointer, as a pointer to its typed struct
+pub fn vtable_access<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    dyn_ty: Ty<'tcx>,
+) -> Vec<VariableAccess> {
+    let vtable_ty = vtable_type(fn_cx, dyn_ty);
+
+    vec![
+        VariableAccess::Field {
+            name: FAT_PTR_META_FIELD.to_string(),
+        },
+        VariableAccess::Cast {
+            ty: CType::Pointer(Box::new(vtable_ty)),
+        },
+        VariableAccess::Dereference,
+    ]
+}
+
+/// The name of the vtable field holding entry `idx`, e.g. the method called by a virtual call
+pub fn entry_field<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    dyn_ty: Ty<'tcx>,
+    idx: usize,
+) -> VariableAccess {
+    let CType::Struct(info) = vtable_type(fn_cx, dyn_ty) else {
+        unreachable!("vtables are structs");
+    };
+
+    VariableAccess::Field {
+        name: fn_cx
+            .ongoing_codegen
+            .context
+            .get_field_name_for_struct(&info, idx)
+            .unwrap(),
+    }
+}
+
+pub fn size_field() -> VariableAccess {
+    VariableAccess::Field {
+        name: SIZE_FIELD.to_string(),
+    }
+}
+
+pub fn align_field() -> VariableAccess {
+    VariableAccess::Field {
+        name: ALIGN_FIELD.to_string(),
+    }
+}
+
+pub fn drop_in_place_field() -> VariableAccess {
+    VariableAccess::Field {
+        name: DROP_IN_PLACE_FIELD.to_string(),
+    }
+}
+
+fn vtable_entries<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    self_ty: Ty<'tcx>,
+    dyn_ty: Ty<'tcx>,
+) -> &'tcx [VtblEntry<'tcx>] {
+    let TyKind::Dynamic(predicates, ..) = dyn_ty.kind() else {
+        panic!("Expected trait object, found {:?}", dyn_ty);
+    };
+
+    match predicates.principal() {
+        Some(principal) => {
+            let trait_ref = principal.with_self_ty(fn_cx.tcx, self_ty);
+            let trait_ref = fn_cx.tcx.erase_regions(trait_ref);
+            fn_cx.tcx.vtable_entries(trait_ref)
+        }
+        // only auto traits, e.g. dyn Send
+        None => COMMON_VTABLE_ENTRIES,
+    }
+}
+
+// e.g. codegenc_vtable_dyn_core__fmt__Debug
+fn vtable_type_name<'tcx, 'ccx>(fn_cx: &CodegenFunctionCx<'tcx, 'ccx>, dyn_ty: Ty<'tcx>) -> String {
+    let TyKind::Dynamic(predicates, ..) = dyn_ty.kind() else {
+        panic!("Expected trait object, found {:?}", dyn_ty);
+    };
+
+    // auto traits don't have vtable entries, so they share the vtables of the principal
+    let predicates = fn_cx.tcx.mk_poly_existential_predicates_from_iter(
+        predicates
+            .iter()
+            .filter(|p| !matches!(p.skip_binder(), ExistentialPredicate::AutoTrait(_))),
+    );
+    let dyn_ty = Ty::new_dynamic(
+        fn_cx.tcx,
+        predicates,
+        fn_cx.tcx.lifetimes.re_erased,
+        ty::Dyn,
+    );
+
+    let name = with_no_trimmed_paths!(dyn_ty.to_string())
+        .replace("::", "__")
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
+
+    format!("codegenc_vtable_{name}")
+}
+
+/// The trait object type of a vtable allocation's principal trait, `dyn Trait` or `dyn` of only auto traits
+pub fn dyn_type<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    principal: Option<ty::PolyExistentialTraitRef<'tcx>>,
+) -> Ty<'tcx> {
+    let predicates = principal
+        .map(|principal| principal.map_bound(ExistentialPredicate::Trait))
+        .into_iter();
+
+    Ty::new_dynamic(
+        tcx,
+        tcx.mk_poly_existential_predicates_from_iter(predicates),
+        tcx.lifetimes.re_erased,
+        ty::Dyn,
+    )
+}
+
+fn trait_object_type<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    trait_ref: ty::PolyTraitRef<'tcx>,
+) -> Ty<'tcx> {
+    let principal =
+        trait_ref.map_bound(|trait_ref| ExistentialTraitRef::erase_self_ty(fn_cx.tcx, trait_ref));
+
+    dyn_type(fn_cx.tcx, Some(principal))
+}
+
+// drop_in_place takes a pointer to the value, and returns unit
+fn drop_fn_type<'tcx, 'ccx>(fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>) -> CType {
+    CType::FunctionPtr(Box::new(CFuncPtrInfo {
+        args: vec![void_ptr()],
+        ret: Box::new(fn_cx.rust_to_c_type(&fn_cx.tcx.types.unit)),
+    }))
+}
+
+// the receiver is erased to void*, which matches every implementor of the method
+fn method_type<'tcx, 'ccx>(
+    fn_cx: &mut CodegenFunctionCx<'tcx, 'ccx>,
+    instance: Instance<'tcx>,
+) -> CType {
+    let sig = instance
+        .ty(fn_cx.tcx, ParamEnv::reveal_all())
+        .fn_sig(fn_cx.tcx);
+    let sig = fn_cx
+        .tcx
+        .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), sig);
+
+    fn_cx.erased_fn_pointer_type(&sig)
+}
+
+fn fn_ref<'tcx, 'ccx>(
+    fn_cx: &CodegenFunctionCx<'tcx, 'ccx>,
+    instance: Instance<'tcx>,
+    ty: CType,
+) -> Expression {
+    Expression::Cast {
+        ty,
+        value: Box::new(Expression::Constant {
+            value: format_fn_name(&fn_cx.tcx.symbol_name(instance)),
+        }),
+    }
+}
+
+fn null(ty: CType) -> Expression {
+    Expression::Cast {
+        ty,
+        value: Box::new(Expression::const_int(0)),
+    }
+}
+
+fn void_ptr() -> CType {
+    CType::Pointer(Box::new(CType::Void))
+}
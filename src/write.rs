@@ -1,9 +1,11 @@
 use std::{fs::File, io::Write};
 
 use crate::alloc;
+use crate::asm;
 use crate::bb::BasicBlock;
 use crate::bb::BasicBlockIdentifier;
 use crate::crepr::Representable;
+use crate::definition;
 use crate::definition::CVarDef;
 use crate::expression::Expression;
 use crate::function;
@@ -37,6 +39,15 @@ pub fn write_includes(
         .unwrap();
 }
 
+// Write feature test macros, they have to come before the first include
+pub fn write_feature_macros(defines: &Vec<header::CDefine>, file: &mut File) {
+    for define in defines {
+        file.write_all(define.default_repr_str().as_bytes())
+            .unwrap();
+        file.write_all(b"\n").unwrap();
+    }
+}
+
 pub fn write_representables(reprs: &Vec<impl Representable>, file: &mut File) {
     let reprs = reprs
         .iter()
@@ -48,11 +59,26 @@ pub fn write_representables(reprs: &Vec<impl Representable>, file: &mut File) {
     file.write_all(reprs.join("\n\n").as_bytes()).unwrap();
 }
 
+// Write file-scope asm blocks
+pub fn write_global_asm(global_asm: &Vec<asm::CGlobalAsm>, file: &mut File) {
+    write_representables(global_asm, file)
+}
+
 // Write Defines
 pub fn write_defines(defines: &Vec<header::CDefine>, file: &mut File) {
     write_representables(defines, file)
 }
 
+// Write global variables
+pub fn write_globals(globals: &Vec<definition::CVarDecl>, file: &mut File) {
+    write_representables(globals, file)
+}
+
+// Write typed vtables
+pub fn write_vtables(vtables: &Vec<definition::CVarDecl>, file: &mut File) {
+    write_representables(vtables, file)
+}
+
 // Write function prototypes
 pub fn write_prototypes(functions: &Vec<function::CFunction>, file: &mut File) {
     let prototypes = functions
@@ -151,6 +177,16 @@ pub fn write_functions(functions: &mut Vec<function::CFunction>, file: &mut File
     file.write_all(functions.join("\n\n").as_bytes()).unwrap();
 }
 
+// Write the forward declarations of all statics, then their definitions
 pub fn write_statics(statics: &Vec<alloc::StaticAllocation>, file: &mut File) {
+    let declarations = statics
+        .iter()
+        .filter_map(|s| s.as_forward_declaration())
+        .collect::<Vec<String>>();
+
+    // Write newline
+    file.write_all(b"\n\n").unwrap();
+    file.write_all(declarations.join("\n").as_bytes()).unwrap();
+
     write_representables(statics, file)
 }
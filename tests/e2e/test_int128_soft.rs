@@ -0,0 +1,45 @@
+// built by `make test_soft_int128` with CODEGENC_SOFT_INT128 defined,
+// so every 128-bit operation goes through the struct based fallback
+fn main() {
+    let big: u128 = 1 << 100 | 3;
+    let neg: i128 = -(1 << 90);
+
+    check(big.checked_add(big).is_some());
+    check(u128::MAX.checked_add(1).is_none());
+    check(0u128.checked_sub(1).is_none());
+    check(i128::MIN.checked_sub(1).is_none());
+    check(i128::MAX.checked_mul(2).is_none());
+    check(neg.checked_mul(-1) == Some(1 << 90));
+    check(i128::MIN.checked_mul(-1).is_none());
+
+    check(1e30f64 as u128 == 1_000_000_000_000_000_019_884_624_838_656);
+    check(-1e30f64 as i128 == -1_000_000_000_000_000_019_884_624_838_656);
+    check(f64::MAX as u128 == u128::MAX);
+    check(f64::MIN as i128 == i128::MIN);
+    check(f64::NAN as i128 == 0);
+
+    check(big.count_ones() == 3);
+    check(big.leading_zeros() == 27);
+    check(big.trailing_zeros() == 0);
+    check(big.swap_bytes() == 0x0300_0000_0000_0000_0000_0000_1000_0000);
+    check(1u128.reverse_bits() == 1 << 127);
+    check(big.rotate_left(28) == 1 | 3 << 28);
+
+    check(describe(big) == 1);
+    check(describe(u128::MAX) == 2);
+    check(describe(7) == 0);
+}
+
+fn describe(value: u128) -> u32 {
+    match value {
+        0x10_0000_0000_0000_0000_0000_0003 => 1,
+        u128::MAX => 2,
+        _ => 0,
+    }
+}
+
+fn check(ok: bool) {
+    if !ok {
+        panic!()
+    }
+}
@@ -0,0 +1,40 @@
+// statics pointing to each other, and to a vtable, have to compile as C
+trait Name {
+    fn name(&self) -> u8;
+}
+
+impl Name for u8 {
+    fn name(&self) -> u8 {
+        *self
+    }
+}
+
+struct Node {
+    value: u8,
+    next: &'static Node,
+    name: &'static dyn Name,
+}
+
+static A: Node = Node {
+    value: 1,
+    next: &B,
+    name: &b'a',
+};
+static B: Node = Node {
+    value: 2,
+    next: &A,
+    name: &b'b',
+};
+
+fn main() {
+    check(A.next.value == 2);
+    check(B.next.next.value == 1);
+    check(A.name.name() == b'a');
+    check(A.next.name.name() == b'b');
+}
+
+fn check(ok: bool) {
+    if !ok {
+        panic!()
+    }
+}
@@ -0,0 +1,33 @@
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use core::intrinsics;
+
+#[no_mangle]
+// CHECK-LABEL: test_load(
+unsafe fn test_load(p: *const u64) -> u64 {
+    // CHECK: atomic_load_explicit((_Atomic uint64_t*){{.*}}, memory_order_acquire)
+    intrinsics::atomic_load_acquire(p)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_fetch_add(
+unsafe fn test_fetch_add(p: *mut u32, v: u32) -> u32 {
+    // CHECK: atomic_fetch_add_explicit({{.*}}, memory_order_relaxed)
+    intrinsics::atomic_xadd_relaxed(p, v)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_compare_exchange(
+unsafe fn test_compare_exchange(p: *mut u32, old: u32, new: u32) -> bool {
+    // CHECK: codegenc_atomic_cxchg_{{.*}}, memory_order_seq_cst, memory_order_acquire)
+    intrinsics::atomic_cxchg_seqcst_acquire(p, old, new).1
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_fence(
+fn test_fence() {
+    // CHECK: atomic_thread_fence(memory_order_seq_cst)
+    unsafe { intrinsics::atomic_fence_seqcst() }
+}
@@ -17,3 +17,10 @@ fn test_cast() -> usize {
 
     num2 + num3
 }
+
+#[no_mangle]
+// CHECK-LABEL: test_float_to_int(
+fn test_float_to_int(value: f64) -> u8 {
+    // CHECK: float_to_int_f64_u8({{[a-zA-Z0-9_]+}});
+    value as u8
+}
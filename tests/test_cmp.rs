@@ -0,0 +1,24 @@
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use std::cmp::Ordering;
+use std::intrinsics::three_way_compare;
+
+#[no_mangle]
+// CHECK-LABEL: test_cmp_signed(
+fn test_cmp_signed(a: i32, b: i32) -> Ordering {
+    // CHECK: ({{[a-zA-Z0-9_]+}} > {{[a-zA-Z0-9_]+}}) - ({{[a-zA-Z0-9_]+}} < {{[a-zA-Z0-9_]+}})
+    three_way_compare(a, b)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_cmp_match(
+fn test_cmp_match(a: u64, b: u64) -> u8 {
+    // CHECK: case -1:
+    match three_way_compare(a, b) {
+        Ordering::Less => 0,
+        Ordering::Equal => 1,
+        Ordering::Greater => 2,
+    }
+}
@@ -0,0 +1,35 @@
+#![crate_type = "lib"]
+
+pub struct Guard {
+    value: u32,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.value = 0;
+    }
+}
+
+pub struct Pair {
+    first: Guard,
+    second: Guard,
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_drop(
+fn test_drop(value: u32) -> u32 {
+    // CHECK: {{[a-zA-Z0-9_]+}}drop_in_place{{[a-zA-Z0-9_]+}}(&{{[a-zA-Z0-9_]+}});
+    let guard = Guard { value };
+    guard.value
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_drop_fields(
+fn test_drop_fields(value: u32) -> u32 {
+    // CHECK: {{[a-zA-Z0-9_]+}}drop_in_place{{[a-zA-Z0-9_]+}}(&{{[a-zA-Z0-9_]+}});
+    let pair = Pair {
+        first: Guard { value },
+        second: Guard { value },
+    };
+    pair.first.value + pair.second.value
+}
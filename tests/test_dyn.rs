@@ -0,0 +1,52 @@
+#![crate_type = "lib"]
+#![feature(trait_upcasting)]
+
+pub trait Speak {
+    fn speak(&self) -> u32;
+}
+
+impl Speak for u32 {
+    fn speak(&self) -> u32 {
+        *self
+    }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_dyn_coerce(
+fn test_dyn_coerce(a: &u32) -> &dyn Speak {
+    // CHECK: &VTABLE_
+    a
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_dyn_call(
+fn test_dyn_call(a: &dyn Speak) -> u32 {
+    // CHECK: (*((codegenc_vtable_{{.*}}Speak*){{.*}}.meta)).method_3_speak(
+    a.speak()
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_dyn_size(
+fn test_dyn_size(a: &dyn Speak) -> usize {
+    // CHECK: .meta)).size
+    core::mem::size_of_val(a)
+}
+
+pub trait Loud: Speak {
+    fn shout(&self) -> u32;
+}
+
+impl Loud for u32 {
+    fn shout(&self) -> u32 {
+        *self * 2
+    }
+}
+
+const LOUD: &dyn Loud = &7;
+
+#[no_mangle]
+// CHECK-LABEL: test_dyn_const_upcast(
+fn test_dyn_const_upcast() -> &'static dyn Speak {
+    // CHECK: (codegenc_fat_ptr){ (void*){{.*}}, (void*){{.*}} }
+    LOUD
+}
@@ -1,4 +1,5 @@
 #![crate_type = "lib"]
+#![feature(f16, f128)]
 
 #[no_mangle]
 // CHECK-LABEL: test_float() {
@@ -10,3 +11,27 @@ fn test_float() -> (f32, f64) {
 
     (a, b)
 }
+
+#[no_mangle]
+// CHECK-LABEL: test_float_literal(
+fn test_float_literal() -> f64 {
+    // CHECK: = -0x1.8p+1;
+    -3.0
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_half_quad(
+fn test_half_quad(a: f16, b: f128) -> (f16, f128) {
+    // CHECK: _Float16 {{[a-zA-Z0-9_]+}}
+    // CHECK: codegenc_f128 {{[a-zA-Z0-9_]+}}
+    // CHECK: codegenc_f128_lit(0x1.4p+1)
+    (a, b * 2.5)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_float_rem(
+fn test_float_rem(a: f32, b: f64) -> f64 {
+    // CHECK: fmodf(
+    // CHECK: fmod(
+    (a % a) as f64 + b % b
+}
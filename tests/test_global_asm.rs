@@ -0,0 +1,18 @@
+#![crate_type = "lib"]
+
+use std::arch::global_asm;
+
+const MAGIC: u32 = 42;
+
+// CHECK: __asm__(
+// CHECK: ".globl trampoline\n"
+// CHECK-NEXT: "trampoline:\n"
+// CHECK-NEXT: "mov eax, 42\n"
+// CHECK-NEXT: "ret\n"
+global_asm!(
+    ".globl trampoline",
+    "trampoline:",
+    "mov eax, {}",
+    "ret",
+    const MAGIC
+);
@@ -0,0 +1,46 @@
+#![crate_type = "lib"]
+
+use std::arch::asm;
+
+// CHECK-LABEL: add_asm(
+// CHECK: __asm__ volatile ("{{.*}}add %q0, %q1{{.*}}" : "=&r"({{.*}}) : "0"({{.*}}), "r"({{.*}}) : "cc")
+#[no_mangle]
+pub fn add_asm(a: u64, b: u64) -> u64 {
+    let mut x = a;
+    unsafe {
+        asm!("add {0}, {1}", inout(reg) x, in(reg) b, options(nomem, nostack));
+    }
+    x
+}
+
+// CHECK-LABEL: read_eax(
+// CHECK: __asm__ volatile ("{{.*}}mov eax, 7{{.*}}" : "=a"({{.*}}) :  : "memory", "cc")
+#[no_mangle]
+pub fn read_eax() -> u32 {
+    let out: u32;
+    unsafe {
+        asm!("mov eax, 7", lateout("eax") out);
+    }
+    out
+}
+
+// CHECK-LABEL: dialect_chars(
+// CHECK: __asm__ volatile ("{{.*}}nop # %{a%|b%}{{.*}}" :  :  : )
+#[no_mangle]
+pub fn dialect_chars() {
+    unsafe {
+        asm!("nop # {{a|b}}", options(nomem, nostack, preserves_flags));
+    }
+}
+
+// CHECK-LABEL: early_r8(
+// CHECK: __asm__("r8")
+// CHECK: "=&r"
+#[no_mangle]
+pub fn early_r8() -> u64 {
+    let out: u64;
+    unsafe {
+        asm!("mov r8, 1", out("r8") out, options(nomem, nostack));
+    }
+    out
+}
@@ -11,8 +11,33 @@ fn test_signed_int() -> (i8, i16, i32, i64, i128) {
     let b32: i32 = 0;
     // CHECK: int64_t {{[a-zA-Z0-9_]+}};
     let b64: i64 = 0;
-    // CHECK: __int128_t {{[a-zA-Z0-9_]+}};
+    // CHECK: codegenc_i128 {{[a-zA-Z0-9_]+}};
     let b128: i128 = 0;
 
     (b8, b16, b32, b64, b128)
 }
+
+#[no_mangle]
+// CHECK-LABEL: test_i128_ops(
+fn test_i128_ops(a: i128, b: i64) -> i128 {
+    // CHECK: codegenc_int128_from_i(codegenc_i128, {{.*}})
+    // CHECK: codegenc_int128_xor(
+    // CHECK: codegenc_int128_not(
+    !(a ^ b as i128)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_shifts(
+fn test_shifts(a: i8, n: u32) -> i8 {
+    // CHECK: (int8_t)((uint32_t){{.*}} << (uint32_t)({{.*}} & 7))
+    // CHECK: (int8_t)codegenc_ashr(
+    a.wrapping_shl(n) >> 3
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_div_rem(
+fn test_div_rem(a: i32, b: i32) -> i32 {
+    // CHECK: div_i32(
+    // CHECK: mod_i32(
+    a / b + a % b
+}
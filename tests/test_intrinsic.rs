@@ -0,0 +1,61 @@
+#![crate_type = "lib"]
+#![feature(core_intrinsics, f128)]
+#![allow(internal_features)]
+
+use core::intrinsics;
+
+#[no_mangle]
+// CHECK-LABEL: test_ctpop(
+fn test_ctpop(a: u32) -> u32 {
+    // CHECK: codegenc_ctpop_u32(
+    intrinsics::ctpop(a) as u32
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_bswap(
+fn test_bswap(a: u64) -> u64 {
+    // CHECK: codegenc_bswap_u64(
+    intrinsics::bswap(a)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_copy(
+unsafe fn test_copy(src: *const u32, dst: *mut u32, count: usize) {
+    // CHECK: memcpy({{.*}} * 4);
+    intrinsics::copy_nonoverlapping(src, dst, count)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_sqrt(
+fn test_sqrt(a: f64) -> f64 {
+    // CHECK: sqrt(
+    unsafe { intrinsics::sqrtf64(a) }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_sqrt_f128(
+fn test_sqrt_f128(a: f128) -> f128 {
+    // CHECK: codegenc_f128_fn(sqrt)(
+    unsafe { intrinsics::sqrtf128(a) }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_wrapping_mul(
+fn test_wrapping_mul(a: u8, b: u8) -> u8 {
+    // CHECK: = (uint8_t)((uint32_t){{[a-zA-Z0-9_]+}} * (uint32_t){{[a-zA-Z0-9_]+}});
+    intrinsics::wrapping_mul(a, b)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_transmute(
+fn test_transmute(a: u32) -> f32 {
+    // CHECK: = (*((float*)&{{[a-zA-Z0-9_]+}}));
+    unsafe { intrinsics::transmute(a) }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_transmute_constant(
+fn test_transmute_constant() -> f32 {
+    // CHECK: = (*((float*)&(uint32_t){ {{.+}} }));
+    unsafe { intrinsics::transmute::<u32, f32>(0x3f800000) }
+}
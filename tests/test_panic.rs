@@ -0,0 +1,15 @@
+#![crate_type = "lib"]
+
+#[no_mangle]
+// CHECK-LABEL: test_bounds_check(
+fn test_bounds_check(a: [u32; 4], i: usize) -> u32 {
+    // CHECK: codegenc_panic_bounds_check({{.*}}, "{{.*}}test_panic.rs", {{[0-9]+}}, {{[0-9]+}});
+    a[i]
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_overflow(
+fn test_overflow(a: u32, b: u32) -> u32 {
+    // CHECK: codegenc_panic("attempt to add with overflow", "{{.*}}test_panic.rs", {{[0-9]+}}, {{[0-9]+}});
+    a + b
+}
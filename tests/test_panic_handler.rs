@@ -0,0 +1,41 @@
+#![crate_type = "lib"]
+
+#[no_mangle]
+// CHECK-LABEL: test_explicit_panic(
+fn test_explicit_panic() {
+    // CHECK: codegenc_panic("explicit panic", "{{.*}}test_panic_handler.rs", {{[0-9]+}}, {{[0-9]+}});
+    panic!()
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_unreachable(
+fn test_unreachable(a: u32) -> u32 {
+    // CHECK: codegenc_panic("internal error: entered unreachable code", "{{.*}}test_panic_handler.rs", {{[0-9]+}}, {{[0-9]+}});
+    if a > 10 {
+        unreachable!()
+    }
+    a
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_panic_message(
+fn test_panic_message() {
+    // CHECK: codegenc_panic("something went wrong", "{{.*}}test_panic_handler.rs", {{[0-9]+}}, {{[0-9]+}});
+    panic!("something went wrong")
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_assert_eq(
+fn test_assert_eq(a: u32, b: u32) {
+    // CHECK-NOT: assert_failed
+    // CHECK: codegenc_panic("assertion `left == right` failed", "{{.*}}test_panic_handler.rs", {{[0-9]+}}, {{[0-9]+}});
+    assert_eq!(a, b)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_formatted_panic(
+fn test_formatted_panic(a: u32) {
+    // CHECK-NOT: panic_fmt
+    // CHECK: codegenc_panic("bad value: ", "{{.*}}test_panic_handler.rs", {{[0-9]+}}, {{[0-9]+}});
+    panic!("bad value: {}", a)
+}
@@ -0,0 +1,80 @@
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use std::intrinsics::{aggregate_raw_ptr, offset};
+
+#[no_mangle]
+// CHECK-LABEL: test_offset(
+fn test_offset(p: *const u32, n: usize) -> *const u32 {
+    // CHECK: (uint32_t*)((int8_t*){{[a-zA-Z0-9_]+}} + ({{[a-zA-Z0-9_]+}} * 4))
+    unsafe { offset(p, n) }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_offset_zst(
+fn test_offset_zst(p: *const (), n: isize) -> *const () {
+    // CHECK-NOT: int8_t*
+    unsafe { offset(p, n) }
+}
+
+fn add_one(x: u32) -> u32 {
+    x + 1
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_fn_pointer(
+fn test_fn_pointer() -> fn(u32) -> u32 {
+    // CHECK: (uint32_t (*)(uint32_t)){{.*}}add_one
+    add_one
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_closure_fn_pointer(
+fn test_closure_fn_pointer() -> fn(u32) -> u32 {
+    // CHECK: = closure_fn_{{.*}};
+    |x| x * 2
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_ptr_int_round_trip(
+fn test_ptr_int_round_trip(p: *const u32) -> *const u8 {
+    // CHECK: (uint64_t)(uintptr_t)
+    let addr = p as usize;
+    // CHECK: (uint8_t*)(uintptr_t)
+    addr as *const u8
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_fat_to_thin(
+fn test_fat_to_thin(p: *const [u32]) -> *const u8 {
+    // CHECK: ((uint8_t*) ({{.*}}.data))
+    p as *const u8
+}
+
+pub struct Wrapper<T: ?Sized> {
+    len: u8,
+    data: T,
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_unsize_struct_tail(
+fn test_unsize_struct_tail(w: &Wrapper<[u8; 4]>) -> &Wrapper<[u8]> {
+    // CHECK: (codegenc_fat_ptr){ {{.*}}, 4 }
+    w
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_slice_from_raw_parts(
+fn test_slice_from_raw_parts(p: *const u8, len: usize) -> *const [u8] {
+    // CHECK: (codegenc_fat_ptr){ {{.*}}void*{{.*}} }
+    unsafe { aggregate_raw_ptr(p, len) }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_thin_from_raw_parts(
+fn test_thin_from_raw_parts(p: *const u8) -> *const u32 {
+    // CHECK-NOT: codegenc_fat_ptr
+    // CHECK: (uint32_t*)
+    unsafe { aggregate_raw_ptr(p, ()) }
+}
@@ -0,0 +1,48 @@
+#![crate_type = "lib"]
+#![feature(repr_simd, core_intrinsics)]
+#![allow(internal_features)]
+
+use core::intrinsics::simd;
+
+#[repr(simd)]
+#[derive(Clone, Copy)]
+pub struct U32x4(u32, u32, u32, u32);
+
+#[repr(simd)]
+#[derive(Clone, Copy)]
+pub struct I32x4(i32, i32, i32, i32);
+
+#[no_mangle]
+// CHECK-LABEL: test_simd_add(
+unsafe fn test_simd_add(a: U32x4, b: U32x4) -> U32x4 {
+    // CHECK: = codegenc_simd_add_{{.*}}(
+    simd::simd_add(a, b)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_simd_eq(
+unsafe fn test_simd_eq(a: U32x4, b: U32x4) -> I32x4 {
+    // CHECK: = codegenc_simd_eq_{{.*}}(
+    simd::simd_eq(a, b)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_simd_extract(
+unsafe fn test_simd_extract(a: U32x4) -> u32 {
+    // CHECK: .wrapee[2]
+    simd::simd_extract(a, 2)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_simd_reduce_add(
+unsafe fn test_simd_reduce_add(a: U32x4) -> u32 {
+    // CHECK: .wrapee[0] + {{.*}}.wrapee[1]
+    simd::simd_reduce_add_unordered(a)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_simd_lane(
+fn test_simd_lane(a: U32x4) -> u32 {
+    // CHECK: .wrapee[1]
+    a.1
+}
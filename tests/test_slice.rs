@@ -8,3 +8,21 @@ fn test_slice() -> &'static str {
 
     a
 }
+
+#[no_mangle]
+// CHECK-LABEL: slice_rest(
+fn slice_rest(s: &[u32]) -> &[u32] {
+    // CHECK: (codegenc_fat_ptr){ {{.*}}.data)) + 1, {{.*}}.meta) - 1) }
+    match s {
+        [_, rest @ ..] => rest,
+        [] => s,
+    }
+}
+
+#[no_mangle]
+// CHECK-LABEL: array_rest(
+fn array_rest(a: [u32; 4]) -> [u32; 3] {
+    // CHECK: (*(({{.*}}*)&{{[a-zA-Z0-9_]+}}.wrapee[1]))
+    let [_, rest @ ..] = a;
+    rest
+}
@@ -0,0 +1,34 @@
+#![crate_type = "lib"]
+#![feature(const_refs_to_static)]
+
+#[no_mangle]
+static mut COUNTER: u32 = 0;
+
+#[no_mangle]
+// CHECK-LABEL: test_static() {
+fn test_static() -> u32 {
+    unsafe {
+        // CHECK: &COUNTER
+        COUNTER = 5;
+        COUNTER
+    }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_str_table(
+fn test_str_table() -> &'static [&'static str] {
+    // CHECK: &ALLOC_
+    &["a", "bc"]
+}
+
+#[no_mangle]
+static PAIR: (u32, u32) = (1, 2);
+
+const SECOND: &u32 = &PAIR.1;
+
+#[no_mangle]
+// CHECK-LABEL: test_static_field(
+fn test_static_field() -> u32 {
+    // CHECK: (int8_t*){{.*}}&PAIR{{.*}} + 4
+    *SECOND
+}
@@ -11,8 +11,29 @@ fn test_unsigned_int() -> (u8, u16, u32, u64, u128) {
     let b32: u32 = 0;
     // CHECK: uint64_t {{[a-zA-Z0-9_]+}};
     let b64: u64 = 0;
-    // CHECK: __uint128_t {{[a-zA-Z0-9_]+}};
+    // CHECK: codegenc_u128 {{[a-zA-Z0-9_]+}};
     let b128: u128 = 0;
 
     (b8, b16, b32, b64, b128)
 }
+
+const HIGH_AND_LOW: u128 = 1 << 64 | 2;
+
+#[no_mangle]
+// CHECK-LABEL: test_u128_ops(
+fn test_u128_ops(a: u128, b: u128) -> u128 {
+    // CHECK: codegenc_int128_or({{.*}}, codegenc_int128_lit(codegenc_u128, 0x1u, 0x2u))
+    // CHECK: codegenc_int128_and(
+    (a | HIGH_AND_LOW) & b
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_u128_match(
+fn test_u128_match(a: u128) -> u8 {
+    // CHECK-NOT: switch
+    // CHECK: if (codegenc_int128_eq({{.*}}, codegenc_int128_lit(codegenc_u128, 0x1u, 0x2u))) goto
+    match a {
+        HIGH_AND_LOW => 1,
+        _ => 0,
+    }
+}
@@ -0,0 +1,14 @@
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use std::intrinsics::catch_unwind;
+
+#[no_mangle]
+// CHECK-LABEL: test_catch_unwind(
+fn test_catch_unwind(try_fn: fn(*mut u8), data: *mut u8, catch_fn: fn(*mut u8, *mut u8)) -> i32 {
+    // CHECK-NOT: codegenc_try(
+    // CHECK: {{[a-zA-Z0-9_]+}}({{[a-zA-Z0-9_]+}});
+    // CHECK: {{[a-zA-Z0-9_]+}} = 0;
+    unsafe { catch_unwind(try_fn, data, catch_fn) }
+}
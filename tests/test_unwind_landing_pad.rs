@@ -0,0 +1,44 @@
+// ENV: C_CODEGEN_PANIC=UNWIND
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use std::intrinsics::catch_unwind;
+
+pub struct Guard {
+    value: u32,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.value = 0;
+    }
+}
+
+fn may_panic(value: u32) {
+    if value == 0 {
+        panic!()
+    }
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_landing_pad(
+fn test_landing_pad(value: u32) {
+    // CHECK: codegenc_unwind_frame unwind_frame;
+    // CHECK: unwind_frame.prev = codegenc_unwind_top;
+    // CHECK: codegenc_unwind_top = &unwind_frame;
+    // CHECK: if (setjmp(unwind_frame.buf)) goto [[CLEANUP:bb[0-9]+]];
+    // CHECK: codegenc_unwind_top = unwind_frame.prev;
+    // CHECK: [[CLEANUP]]:
+    // CHECK: drop_in_place{{[a-zA-Z0-9_]+}}(&{{[a-zA-Z0-9_]+}});
+    // CHECK: codegenc_resume_unwind();
+    let _guard = Guard { value };
+    may_panic(value);
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_catch_unwind(
+fn test_catch_unwind(try_fn: fn(*mut u8), data: *mut u8, catch_fn: fn(*mut u8, *mut u8)) -> i32 {
+    // CHECK: codegenc_try({{[a-zA-Z0-9_]+}}, {{[a-zA-Z0-9_]+}}, {{[a-zA-Z0-9_]+}})
+    unsafe { catch_unwind(try_fn, data, catch_fn) }
+}
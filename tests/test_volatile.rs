@@ -0,0 +1,40 @@
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use core::intrinsics;
+
+#[no_mangle]
+// CHECK-LABEL: test_read_volatile(
+unsafe fn test_read_volatile(p: *const u32) -> u32 {
+    // CHECK: = *(volatile uint32_t*)
+    intrinsics::volatile_load(p)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_read_volatile_ptr(
+unsafe fn test_read_volatile_ptr(p: *const *mut u8) -> *mut u8 {
+    // CHECK: = *(uint8_t* volatile*)
+    intrinsics::volatile_load(p)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_write_volatile_fat_ptr(
+unsafe fn test_write_volatile_fat_ptr(p: *mut *const [u8], v: *const [u8]) {
+    // CHECK: *(codegenc_fat_ptr volatile*){{.*}} = {{.*}};
+    intrinsics::volatile_store(p, v)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_write_volatile(
+unsafe fn test_write_volatile(p: *mut u16, v: u16) {
+    // CHECK: *(volatile uint16_t*){{.*}} = {{.*}};
+    intrinsics::volatile_store(p, v)
+}
+
+#[no_mangle]
+// CHECK-LABEL: test_set_volatile(
+unsafe fn test_set_volatile(p: *mut u64, count: usize) {
+    // CHECK: codegenc_volatile_set((volatile uint8_t*){{.*}}, 0, {{.*}} * 8);
+    intrinsics::volatile_set_memory(p, 0, count)
+}